@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Directory apt reads preferences, and thus pins, from.
+pub const PREFERENCES_DIR: &str = "/etc/apt/preferences.d";
+
+/// Pin priority that keeps snapd from being installed again, the only pin
+/// rhino-config writes.
+pub const SNAPD_PIN_PRIORITY: i32 = -10;
+
+/// Name of the file in the state directory which tracks holds and pins.
+pub const TRACKING_FILE: &str = "apt.json";
+
+/// An apt preferences pin for a single package.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Pin {
+    pub package: String,
+    pub priority: i32,
+    pub path: PathBuf,
+}
+
+impl Pin {
+    /// Pin `package` to `priority` in a file of its own.
+    ///
+    /// A negative priority keeps the package from being installed at all.
+    pub fn new(package: &str, priority: i32) -> Self {
+        Pin {
+            package: package.to_string(),
+            priority,
+            path: Path::new(PREFERENCES_DIR).join(format!("rhino-config-{}.pref", package)),
+        }
+    }
+
+    /// The contents of the preferences file.
+    pub fn contents(&self) -> String {
+        format!(
+            "Package: {}\nPin: release a=*\nPin-Priority: {}\n",
+            self.package, self.priority
+        )
+    }
+}
+
+/// The holds and pins rhino-config has added, so that disabling an option
+/// removes exactly what enabling it added.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Tracked {
+    pub holds: BTreeSet<String>,
+    pub pins: Vec<Pin>,
+}
+
+impl Tracked {
+    /// Load the tracked holds and pins, `None` if nothing has been tracked
+    /// yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read {}!", path.display()))?;
+        Ok(Some(
+            serde_json::from_str(&contents)
+                .context(format!("Failed to parse {}!", path.display()))?,
+        ))
+    }
+
+    /// Write the tracked holds and pins to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create {}!", parent.display()))?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .context(format!("Failed to write {}!", path.display()))
+    }
+
+    /// Apply `change` to the tracked holds and pins stored at `path`.
+    pub fn update(path: &Path, change: impl FnOnce(&mut Tracked)) -> Result<()> {
+        let mut tracked = Tracked::load(path)?.unwrap_or_default();
+        change(&mut tracked);
+        tracked.save(path)
+    }
+
+    /// The pins that have been added for `package`.
+    pub fn pins_for<'a>(&'a self, package: &'a str) -> impl Iterator<Item = &'a Pin> {
+        self.pins.iter().filter(move |pin| pin.package == package)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::{Pin, Tracked};
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_tracked(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let path = temp_dir.path().join("state/apt.json");
+
+        // Test that nothing is tracked before anything has been added
+        assert_eq!(Tracked::load(&path)?, None);
+
+        Tracked::update(&path, |tracked| {
+            tracked.holds.insert("snapd".to_string());
+            tracked.pins.push(Pin::new("snapd", -10));
+        })?;
+
+        let tracked = Tracked::load(&path)?.unwrap();
+        assert!(tracked.holds.contains("snapd"));
+        assert_eq!(
+            tracked.pins_for("snapd").next().unwrap().path.to_str(),
+            Some("/etc/apt/preferences.d/rhino-config-snapd.pref")
+        );
+        assert_eq!(tracked.pins_for("flatpak").count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pin_contents() {
+        assert_eq!(
+            Pin::new("snapd", -10).contents(),
+            "Package: snapd\nPin: release a=*\nPin-Priority: -10\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::history;
+use crate::runner::{CommandRunner, ExternalCommand};
+
+/// A tarball of a directory, described by a manifest next to it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Backup {
+    /// The directory that was archived, and where it is restored to.
+    pub source: PathBuf,
+    pub archive: PathBuf,
+    /// When the backup was taken, in seconds since the Unix epoch.
+    pub created: u64,
+    /// The entries at the top of `source`, one per snap for `~/snap`.
+    #[serde(default)]
+    pub contents: Vec<String>,
+}
+
+impl Backup {
+    /// A backup of `source` to be taken into `dir` now.
+    pub fn new(source: &Path, dir: &Path) -> Self { Backup::at(source, dir, history::now()) }
+
+    fn at(source: &Path, dir: &Path, created: u64) -> Self {
+        let name = source
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "backup".to_string());
+        Backup {
+            source: source.to_path_buf(),
+            archive: dir.join(format!("{}-{}.tar.gz", name, created)),
+            created,
+            contents: Vec::new(),
+        }
+    }
+
+    /// The manifest describing the backup.
+    pub fn manifest(&self) -> PathBuf { self.archive.with_extension("").with_extension("json") }
+
+    /// Archive `source` with `tar` and write the manifest, returning `false`
+    /// if there is nothing to back up.
+    pub fn create(&self, runner: &mut dyn CommandRunner) -> Result<bool> {
+        let entries = match fs::read_dir(&self.source) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(error) => {
+                return Err(error).context(format!("Failed to read {}!", self.source.display()))
+            },
+        };
+        let mut contents = entries
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        contents.sort();
+
+        let (parent, name) = self.split()?;
+        if let Some(dir) = self.archive.parent() {
+            fs::create_dir_all(dir).context(format!("Failed to create {}!", dir.display()))?;
+        }
+        runner
+            .run(&ExternalCommand::new(
+                "tar",
+                [
+                    "-czf".to_string(),
+                    self.archive.display().to_string(),
+                    "-C".to_string(),
+                    parent.display().to_string(),
+                    name.to_string_lossy().into_owned(),
+                ],
+            ))
+            .context(format!("Failed to back up {}!", self.source.display()))?;
+
+        let manifest = Backup {
+            contents,
+            ..self.clone()
+        };
+        fs::write(self.manifest(), serde_json::to_string_pretty(&manifest)?)
+            .context(format!("Failed to write {}!", self.manifest().display()))?;
+        Ok(true)
+    }
+
+    /// Extract the archive back into the parent of `source`.
+    pub fn restore(&self, runner: &mut dyn CommandRunner) -> Result<()> {
+        ensure!(
+            self.archive.exists(),
+            "The backup {} no longer exists!",
+            self.archive.display()
+        );
+        let (parent, _) = self.split()?;
+        fs::create_dir_all(parent).context(format!("Failed to create {}!", parent.display()))?;
+        runner
+            .run(&ExternalCommand::new(
+                "tar",
+                [
+                    "-xzf".to_string(),
+                    self.archive.display().to_string(),
+                    "-C".to_string(),
+                    parent.display().to_string(),
+                ],
+            ))
+            .context(format!("Failed to restore {}!", self.source.display()))
+    }
+
+    /// The directory `source` is in and its name.
+    fn split(&self) -> Result<(&Path, &OsStr)> {
+        self.source
+            .parent()
+            .zip(self.source.file_name())
+            .context(format!("Unable to back up {}!", self.source.display()))
+    }
+
+    /// The most recent backup of `source` in `dir`, if any.
+    pub fn latest(dir: &Path, source: &Path) -> Result<Option<Self>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error).context(format!("Failed to read {}!", dir.display())),
+        };
+
+        let mut latest: Option<Backup> = None;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let contents =
+                fs::read_to_string(&path).context(format!("Failed to read {}!", path.display()))?;
+            let backup: Backup = serde_json::from_str(&contents)
+                .context(format!("Failed to parse {}!", path.display()))?;
+            if backup.source.components().eq(source.components())
+                && latest
+                    .as_ref()
+                    .is_none_or(|latest| backup.created > latest.created)
+            {
+                latest = Some(backup);
+            }
+        }
+        Ok(latest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::Backup;
+    use crate::runner::RecordingRunner;
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_create(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let source = temp_dir.path().join("home/snap/");
+        let dir = temp_dir.path().join("backups");
+        let mut runner = RecordingRunner::default();
+
+        // Test that a missing directory is not backed up
+        let backup = Backup::at(&source, &dir, 1);
+        assert!(!backup.create(&mut runner)?);
+        assert!(runner.commands.is_empty());
+
+        for snap in ["hello", "firefox"] {
+            fs::create_dir_all(source.join(snap))?;
+        }
+        assert!(backup.create(&mut runner)?);
+        assert_eq!(
+            runner.command_lines(),
+            [format!(
+                "tar -czf {} -C {} snap",
+                dir.join("snap-1.tar.gz").display(),
+                temp_dir.path().join("home").display()
+            )]
+        );
+        // Test that the manifest lists what was backed up
+        assert_eq!(backup.manifest(), dir.join("snap-1.json"));
+        assert_eq!(
+            Backup::latest(&dir, &source)?.unwrap().contents,
+            ["firefox", "hello"]
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_latest(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let source = temp_dir.path().join("snap");
+        let dir = temp_dir.path().join("backups");
+        fs::create_dir_all(&source)?;
+        let mut runner = RecordingRunner::default();
+
+        assert_eq!(Backup::latest(&dir, &source)?, None);
+
+        for created in [3, 5, 4] {
+            Backup::at(&source, &dir, created).create(&mut runner)?;
+        }
+        // Test that the most recent backup is picked
+        let latest = Backup::latest(&dir, &source)?.unwrap();
+        assert_eq!(latest.created, 5);
+        // Test that backups of other directories are ignored
+        assert_eq!(Backup::latest(&dir, &temp_dir.path().join("other"))?, None);
+
+        // Test that a backup whose archive is gone is not restored
+        assert!(latest.restore(&mut runner).is_err());
+        fs::write(&latest.archive, "")?;
+        latest.restore(&mut runner)?;
+        assert_eq!(
+            runner.command_lines().last().unwrap(),
+            &format!(
+                "tar -xzf {} -C {}",
+                latest.archive.display(),
+                temp_dir.path().display()
+            )
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+//! Runs a single operation of rhino-config as root.
+//!
+//! The operation is read as JSON from the standard input, and has to be one
+//! of the [`Request`](rhino_config::helper::Request)s, whose arguments are
+//! checked before anything is run. Scripts are only run as configured in
+//! `/etc/rhino/config.toml`, or by default, and are downloaded and verified by
+//! the helper itself. Meant to be started by rhino-config through sudo, doas
+//! or pkexec, see the polkit policy in `data/`.
+
+use std::io::{self, Read};
+use std::process;
+
+use anyhow::{ensure, Context, Result};
+use rhino_config::{helper, user, ErrorKind, SystemRunner};
+
+fn run() -> Result<()> {
+    ensure!(user::is_root(), "{} has to be run as root!", helper::HELPER);
+
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .context("Failed to read the request!")?;
+    let request = helper::parse(&input)?;
+    request
+        .execute(&mut SystemRunner::default(), &helper::Trusted::default())
+        .context(format!("Failed to {}!", request))
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {:?}", error);
+        process::exit(ErrorKind::of(&error).exit_code());
+    }
+}
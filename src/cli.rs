@@ -1,4 +1,4 @@
-use clap::{ArgGroup, Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 
 /// Utility to edit rolling rhino remix config
 ///
@@ -11,10 +11,53 @@ use clap::{ArgGroup, Args, Parser, Subcommand};
 /// doing and have read the documentation
 #[derive(Parser)]
 pub struct Cli {
+    /// Output format
+    ///
+    /// `json` prints a single machine-readable document describing the
+    /// changes that were made and any error that occurred. The exit code is
+    /// 3 if an option is already enabled, 4 if it is already disabled, 5 if an
+    /// external command failed, 6 on I/O failures, 7 if an option conflicts
+    /// with one that is already enabled and 8 if a change can not be undone
+    #[clap(long, value_enum, global = true, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// Print the changes that would be made without making them
+    ///
+    /// Lists the config files that would be created or removed and the
+    /// external commands that would be run, then exits without touching the
+    /// system
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+
+    /// Answer yes to every question
+    #[clap(short, long, global = true, conflicts_with = "assume-no")]
+    pub yes: bool,
+
+    /// Answer no to every question
+    ///
+    /// Without this or `--yes`, questions are only asked if stdin is a
+    /// terminal
+    #[clap(long, global = true)]
+    pub assume_no: bool,
+
+    /// Directory holding the config
+    ///
+    /// Defaults to `$XDG_CONFIG_HOME/rhino`, falling back to `~/.rhino` if
+    /// only that exists. Settings in it override the system-wide ones in
+    /// /etc/rhino/config.toml
+    #[clap(long, global = true, env = "RHINO_CONFIG_DIR", value_name = "DIR")]
+    pub config_dir: Option<std::path::PathBuf>,
+
     #[clap(subcommand)]
     pub command: Commands,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Enable a config option, manually or interactively
@@ -31,6 +74,51 @@ pub enum Commands {
     #[clap(subcommand)]
     /// Switch to alternate kernels
     SwitchKernel(SwitchKernel),
+
+    /// Show the state of every config option
+    ///
+    /// Lists every option rhino-config knows about, whether it is enabled or
+    /// disabled, and whether the system actually matches it, e.g. if snapd is
+    /// still installed even though it has been purged
+    Status,
+
+    /// Show where the config is read from
+    ///
+    /// Lists the config files in the order they are applied and which of them
+    /// each option's value comes from
+    ConfigPath,
+
+    /// Show the changes made by rhino-config
+    ///
+    /// Every enable, disable and switch-kernel invocation that changes the
+    /// system is logged to history.jsonl in the config directory, along with
+    /// who ran it, the marker files it touched and the external commands it
+    /// ran. The most recent entries are shown last
+    History(HistoryCommand),
+
+    /// Revert the most recent change
+    ///
+    /// Applies the inverse of the last change in the history that succeeded:
+    /// kernels are switched back, and options are enabled or disabled again
+    /// along with their side effects, e.g. undoing a snap purge reinstalls
+    /// snapd. Undoing twice reverts the undo. Changes that can not be
+    /// reversed, such as uninstalling Pacstall, are refused
+    Undo,
+
+    /// Export the state of every option to a profile
+    ///
+    /// The profile is a small TOML document holding whether each option is
+    /// enabled and which kernel is selected, including the XanMod variant.
+    /// It is written to FILE, or printed if no file is given, and can be
+    /// applied to other machines with `rhino-config import`
+    Export(ExportCommand),
+
+    /// Make the options match a profile
+    ///
+    /// The profile is validated first, then every option that differs from
+    /// it is enabled or disabled just as the matching subcommand would,
+    /// switching kernels as needed. Pass --dry-run to only show what differs
+    Import(ImportCommand),
 }
 
 #[derive(Args)]
@@ -44,7 +132,11 @@ pub struct EnableCommand {
     ///
     /// Interactive mode steps through each configuration one-by-one so that
     /// users with less experience with the command-line are able to easily
-    /// utilise rhino-config
+    /// utilise rhino-config. Each option is shown with its current state and
+    /// can be kept, enabled or disabled. Nothing is changed until the summary
+    /// of pending changes has been confirmed. With `--yes` every disabled
+    /// option is enabled while the kernel is kept, and the changes are
+    /// applied. With `--assume-no` everything is kept as it is
     #[clap(conflicts_with("operations"))]
     #[clap(required_unless_present("operations"))]
     #[clap(short, long)]
@@ -54,18 +146,33 @@ pub struct EnableCommand {
     ///
     /// Pacstall can be enabled via the rhino-config enable command. This will
     /// download, install and keep Pacstall updated. Pacstall is an AUR-like
-    /// package manager for Ubuntu and Ubuntu-based systems
+    /// package manager for Ubuntu and Ubuntu-based systems. If Pacstall is
+    /// not installed yet and an installer is set under [pacstall.installer]
+    /// in config.toml, it is installed right away with that checksum-verified
+    /// script, otherwise rhino-update installs it
     #[clap(short, long)]
     pub pacstall: bool,
 
     /// Remove Snapcraft (snapd) and replace it with Flatpak
     ///
     /// This will be removing snapd from your system. It
-    /// will hold snapd from being reinstalled via apt as well. It will utilise
-    /// Flatpak as a drop-in replacement and will automatically install Flatpak
-    /// as well as the Flathub repositories
+    /// will hold and pin snapd from being reinstalled via apt as well. It will
+    /// utilise Flatpak as a drop-in replacement and will automatically
+    /// install Flatpak as well as the Flathub repositories. Installed snaps
+    /// with a known Flathub equivalent are replaced with it, the others are
+    /// listed in state/unmatched-snaps.txt in the config directory to be
+    /// migrated by hand
     #[clap(short, long)]
     pub snapdpurge: bool,
+
+    /// Back up ~/snap before it is removed by --snapdpurge
+    ///
+    /// The snap directory holds the data of your snaps, such as browser
+    /// profiles. It is archived into a tarball under backups/ in the config
+    /// directory, along with a manifest listing its contents, before anything
+    /// is removed, so that it can be restored when snapd is reinstalled
+    #[clap(long, requires("snapdpurge"))]
+    pub backup: bool,
 }
 
 #[derive(Args)]
@@ -80,31 +187,97 @@ pub struct DisableCommand {
     ///
     /// This disables and uninstalls Pacstall from your system. Pacstall will
     /// no longer be updated and all applications installed via Pacstall will
-    /// have to be manually updated or removed from your system
+    /// have to be manually updated or removed from your system. The
+    /// uninstaller is fetched from `source` under [pacstall.uninstaller] in
+    /// config.toml, a URL or a local file, and only run if it matches the
+    /// SHA-256 checksum pinned there as `sha256`. Without an uninstaller
+    /// Pacstall is disabled but left installed
     #[clap(short, long)]
     pub pacstall: bool,
 
     /// Install Snapcraft (snapd)
     ///
-    /// This reinstalls snapd on your system. Flatpak will still remain on your
-    /// system and will have to be manually removed
+    /// This removes the hold and pin added when snapd was purged and
+    /// reinstalls it on your system. Flatpak will still remain on your
+    /// system and will have to be manually removed. If ~/snap was backed up
+    /// when snapd was purged, you are offered to restore the latest backup
+    /// once snapd is reinstalled
     #[clap(short, long)]
     pub snapdpurge: bool,
 }
+
+#[derive(Args)]
+pub struct HistoryCommand {
+    /// Only show changes to this option, e.g. `snapdpurge`, `liquorix` or
+    /// `xanmod` for every XanMod variant
+    #[clap(long, value_name = "OPTION")]
+    pub option: Option<String>,
+
+    /// Only show changes that failed
+    #[clap(long)]
+    pub failed: bool,
+
+    /// Only show changes made on or after this date, as YYYY-MM-DD in UTC
+    #[clap(long, value_name = "DATE")]
+    pub since: Option<String>,
+
+    /// Only show this many of the most recent changes
+    #[clap(short = 'n', long, value_name = "COUNT")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct ExportCommand {
+    /// File to write the profile to
+    #[clap(value_name = "FILE")]
+    pub file: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ImportCommand {
+    /// Profile to import, as written by `rhino-config export`
+    #[clap(value_name = "FILE")]
+    pub file: std::path::PathBuf,
+
+    /// Back up ~/snap before it is removed, if the profile purges snapd
+    #[clap(long)]
+    pub backup: bool,
+}
+
 #[derive(Subcommand)]
 pub enum SwitchKernel {
     /// Enable custom kernels
-    #[clap(subcommand)]
-    Enable(EnableKernel),
+    ///
+    /// Only one alternate kernel can be enabled at a time
+    Enable(EnableKernelCommand),
 
     /// Disable custom kernels
     #[clap(subcommand)]
     Disable(DisableKernel),
+
+    /// List the alternate kernels
+    ///
+    /// Shows every kernel and XanMod variant that can be enabled, whether it
+    /// is enabled, whether it is installed and whether it is running
+    List,
+}
+
+#[derive(Args)]
+pub struct EnableKernelCommand {
+    /// Replace the kernel that is currently enabled
+    ///
+    /// Without this, enabling a kernel fails if another one is already
+    /// enabled
+    #[clap(long, global = true)]
+    pub replace: bool,
+
+    #[clap(subcommand)]
+    pub kernel: EnableKernel,
 }
 
 #[derive(Subcommand)]
 pub enum EnableKernel {
-    /// Enable the XanMod kernel. Defaults to the stable variant
+    /// Enable a XanMod kernel variant. Defaults to the stable variant
     ///
     /// XanMod is a general-purpose Linux kernel distribution with custom
     /// settings and new features. Built to provide a stable, responsive and
@@ -124,6 +297,12 @@ pub enum EnableKernel {
     /// binary blobs, obfuscated code, or code released under proprietary
     /// licenses
     Libre,
+
+    /// Enable the latest Linux mainline kernel
+    ///
+    /// The mainline kernel is built from the latest upstream Linux release by
+    /// Ubuntu, without any Ubuntu specific patches
+    Mainline,
 }
 
 #[derive(Subcommand)]
@@ -136,6 +315,9 @@ pub enum DisableKernel {
 
     /// Disable the Libre kernel
     Libre,
+
+    /// Disable the mainline kernel
+    Mainline,
 }
 
 #[derive(Args, PartialEq, Default)]
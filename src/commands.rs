@@ -1,3 +1,9 @@
-pub(crate) mod disable;
-pub(crate) mod enable;
-pub(crate) mod switch_kernel;
+pub mod config_path;
+pub mod disable;
+pub mod enable;
+pub mod export;
+pub mod history;
+pub mod import;
+pub mod status;
+pub mod switch_kernel;
+pub mod undo;
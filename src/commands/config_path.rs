@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::{Layer, Store};
+use crate::options::RhinoOption;
+
+/// A config file and whether it exists.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct LayerPath {
+    pub layer: Layer,
+    pub path: PathBuf,
+    pub exists: bool,
+}
+
+/// The state of an option and the layer it comes from.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct OptionSource {
+    pub option: RhinoOption,
+    pub enabled: bool,
+    pub layer: Layer,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigPaths {
+    /// The config files, in the order they are applied.
+    pub layers: Vec<LayerPath>,
+    pub options: Vec<OptionSource>,
+}
+
+/// Collect the config files of `store` and where every option comes from.
+pub fn collect(store: &Store) -> Result<ConfigPaths> {
+    let layers = store
+        .system_file
+        .iter()
+        .map(|path| (Layer::System, path))
+        .chain([(Layer::User, &store.file)])
+        .map(|(layer, path)| LayerPath {
+            layer,
+            path: path.clone(),
+            exists: path.exists(),
+        })
+        .collect();
+
+    let config = store.load()?;
+    let options = RhinoOption::all()
+        .into_iter()
+        .map(|option| {
+            Ok(OptionSource {
+                option,
+                enabled: config.is_enabled(option),
+                layer: store.layer(option)?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(ConfigPaths { layers, options })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use indoc::indoc;
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::OptionSource;
+    use crate::config::{Layer, Store};
+    use crate::options::{Kernel, RhinoOption};
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_collect(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let system_file = temp_dir.path().join("etc/config.toml");
+        fs::create_dir_all(system_file.parent().unwrap())?;
+        fs::write(
+            &system_file,
+            indoc!(
+                r#"
+                version = 1
+
+                [pacstall]
+                enabled = true
+
+                [kernel]
+                selected = "liquorix"
+                "#
+            ),
+        )?;
+        let store = Store::new(&temp_dir.path().join("user")).with_system_file(&system_file);
+        store.set(RhinoOption::Kernel(Kernel::Liquorix), false)?;
+
+        let paths = super::collect(&store)?;
+
+        // Test that the system file is applied before the user one
+        assert_eq!(paths.layers[0].layer, Layer::System);
+        assert!(paths.layers[0].exists);
+        assert_eq!(paths.layers[1].path, store.file);
+
+        let find = |option| paths.options.iter().find(|s| s.option == option).unwrap();
+
+        // Test that options only set system-wide come from the system layer
+        assert_eq!(
+            find(RhinoOption::Pacstall),
+            &OptionSource {
+                option: RhinoOption::Pacstall,
+                enabled: true,
+                layer: Layer::System,
+            }
+        );
+        // Test that the user layer overrides the system one
+        assert_eq!(
+            find(RhinoOption::Kernel(Kernel::Liquorix)),
+            &OptionSource {
+                option: RhinoOption::Kernel(Kernel::Liquorix),
+                enabled: false,
+                layer: Layer::User,
+            }
+        );
+        assert_eq!(find(RhinoOption::Snapdpurge).layer, Layer::Default);
+
+        Ok(())
+    }
+}
@@ -1,130 +1,317 @@
-use std::fs;
 use std::path::Path;
-use std::process::Command;
-
-use anyhow::{ensure, Context, Result};
-
-pub fn pacstall(config_path: &Path) -> Result<()> {
-    fs::remove_file(&config_path).context("Unable to remove pacstall config file!")?;
-    println!("Pacstall has been disabled.");
-    println!("Removing pacstall...");
-
-    // Get the uninstall script from `curl` or `wget` depending upon which is
-    // installed on the system. Capture the output also.
-    let uninstall_script = if Command::new("curl").output()?.status.success() {
-        String::from_utf8(
-            Command::new("curl")
-                .args(["-fsSL", "https://git.io/JEZbi"])
-                .output()?
-                .stdout,
-        )?
-    } else {
-        String::from_utf8(
-            Command::new("wget")
-                .args(["-q", "https://git.io/JEZbi", "-O", "-"])
-                .output()?
-                .stdout,
-        )?
+
+use anyhow::{ensure, Result};
+
+use crate::apt::{Tracked, TRACKING_FILE};
+use crate::backup::Backup;
+use crate::config::Store;
+use crate::error::Error;
+use crate::helper::{Request, ScriptKind};
+use crate::options::RhinoOption;
+use crate::output::Action;
+use crate::plan::{Operation, Step};
+
+/// Name of the cached copy of the Pacstall uninstaller.
+pub const PACSTALL_UNINSTALLER: &str = "pacstall-uninstall.sh";
+
+/// Disable Pacstall and run its uninstaller, if one is configured.
+///
+/// The uninstaller has to be pinned to its checksum in the config. Without
+/// one Pacstall is only disabled, and left installed.
+pub fn pacstall(store: &Store) -> Result<Operation> {
+    let config = store.load()?;
+    ensure!(
+        config.is_enabled(RhinoOption::Pacstall),
+        Error::AlreadyDisabled("Pacstall is already disabled!".to_string())
+    );
+
+    let mut steps = Vec::new();
+    let message = match config.pacstall.uninstaller {
+        Some(uninstaller) => {
+            ensure!(
+                uninstaller.sha256.is_some(),
+                "The Pacstall uninstaller from {} is not pinned! Set `sha256` under \
+                 [pacstall.uninstaller] in {} to the SHA-256 checksum of the copy you have \
+                 reviewed.",
+                uninstaller.source,
+                store.file.display()
+            );
+            // The option stays enabled if the uninstaller can not be fetched
+            steps.push(Step::RunScript {
+                script: uninstaller,
+                kind: ScriptKind::PacstallUninstaller,
+                cache: store.cache_dir().join(PACSTALL_UNINSTALLER),
+            });
+            "Pacstall has been disabled and removed.".to_string()
+        },
+        None => format!(
+            "Pacstall has been disabled, but is still installed as no uninstaller is configured. \
+             Set `source` and `sha256` under [pacstall.uninstaller] in {} to have it removed.",
+            store.file.display()
+        ),
     };
-    ensure!(Command::new("bash")
-        .args(["-c", &uninstall_script])
-        .status()?
-        .success());
-    Ok(())
+    steps.push(Step::disable(store, RhinoOption::Pacstall));
+
+    Ok(Operation {
+        option: RhinoOption::Pacstall,
+        action: Action::Disable,
+        steps,
+        message,
+        journal: None,
+    })
 }
 
-pub fn snapdpurge(config_path: &Path) -> Result<()> {
-    fs::remove_file(&config_path).context("Unable to remove snapdpurge config file!")?;
-    println!("Snapdpurge has been disabled.");
-
-    println!("Reinstalling Snapcraft...");
-
-    ensure!(Command::new("sudo")
-        .args([
-            "apt",
-            "install",
-            "snapd",
-            "gnome-software-plugin-snap",
-            "-y",
-        ])
-        .status()
-        .context("Unable to reinstall snapd!")?
-        .success());
-
-    ensure!(Command::new("sudo")
-        .args(["apt-mark", "unhold", "snapd"])
-        .status()
-        .context("Unable to unhold snapd!")?
-        .success());
-
-    Ok(())
+/// Reinstall snapd, removing the hold and pins added when it was purged.
+///
+/// The option is only disabled once snapd has been reinstalled, a failure is
+/// recorded in `state_dir`. The snap directory is then restored from `restore`
+/// if given, usually the latest backup taken when snapd was purged.
+pub fn snapdpurge(store: &Store, state_dir: &Path, restore: Option<Backup>) -> Result<Operation> {
+    ensure!(
+        store.load()?.is_enabled(RhinoOption::Snapdpurge),
+        Error::AlreadyDisabled("Snapdpurge is already disabled!".to_string())
+    );
+
+    let tracking = state_dir.join(TRACKING_FILE);
+    let tracked = Tracked::load(&tracking)?;
+
+    let mut steps: Vec<Step> = tracked
+        .iter()
+        .flat_map(|tracked| tracked.pins_for("snapd"))
+        .map(|pin| Step::Unpin {
+            pin: pin.clone(),
+            tracking: tracking.clone(),
+        })
+        .collect();
+
+    // Purges from before holds were tracked always held snapd
+    if tracked.is_none_or(|tracked| tracked.holds.contains("snapd")) {
+        steps.push(Step::Unhold {
+            package: "snapd".to_string(),
+            tracking: tracking.clone(),
+        });
+    }
+
+    steps.push(
+        Step::request(Request::InstallSnapd, "Unable to reinstall snapd!")
+            .with_progress("Reinstalling Snapcraft..."),
+    );
+    if let Some(backup) = restore {
+        steps.push(Step::Restore { backup });
+    }
+    steps.push(Step::disable(store, RhinoOption::Snapdpurge));
+
+    Ok(Operation {
+        option: RhinoOption::Snapdpurge,
+        action: Action::Disable,
+        steps,
+        message: "Snapdpurge has been disabled.".to_string(),
+        journal: Some(state_dir.join("snapdpurge-disable.failed")),
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env::var;
     use std::error::Error;
-    use std::fs::File;
-    use std::process::Command;
+    use std::fs;
 
+    use indoc::{formatdoc, indoc};
     use rstest::*;
     use tempfile::{tempdir, TempDir};
 
+    use crate::apt::{Pin, Tracked, TRACKING_FILE};
+    use crate::backup::Backup;
+    use crate::config::Store;
+    use crate::options::RhinoOption;
+    use crate::output::{Format, Report};
+    use crate::plan::Step;
+    use crate::runner::RecordingRunner;
+    use crate::script::sha256;
+
     #[fixture]
     fn temp_dir() -> TempDir { tempdir().unwrap() }
 
+    #[fixture]
+    fn report() -> Report { Report::new(Format::Text, "test") }
+
     #[rstest]
-    fn test_pacstall(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("pacstall");
-        File::create(&config_path)?;
+    fn test_pacstall(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Pacstall);
+        store.set(RhinoOption::Pacstall, true)?;
+        let mut runner = RecordingRunner::default().with_stdout("curl -fsSL", "echo uninstalled");
+
+        // Test that Pacstall is only disabled without an uninstaller
+        let operation = super::pacstall(&store)?;
+        assert_eq!(operation.steps.len(), 1);
+        assert!(!operation.steps.iter().any(Step::needs_root));
+
+        // Test that an uninstaller which is not pinned is refused
+        fs::write(
+            &store.file,
+            indoc! {r#"
+                version = 1
+
+                [pacstall]
+                enabled = true
+
+                [pacstall.uninstaller]
+                source = "https://example.com/uninstall.sh"
+            "#},
+        )?;
+        let error = super::pacstall(&store).unwrap_err();
+        assert!(error.to_string().contains("is not pinned"));
+
+        fs::write(
+            &store.file,
+            formatdoc!(
+                r#"
+                version = 1
 
-        super::pacstall(&config_path)?;
+                [pacstall]
+                enabled = true
+
+                [pacstall.uninstaller]
+                source = "https://example.com/uninstall.sh"
+                sha256 = "{}"
+                "#,
+                sha256(b"echo uninstalled")
+            ),
+        )?;
+        super::pacstall(&store)?.execute(&mut runner, &mut report)?;
         // Test that the config file is deleted
         assert!(!config_path.exists());
+        // Test that the downloaded uninstall script is run from its cached copy
+        let cache = store.cache_dir().join(super::PACSTALL_UNINSTALLER);
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "curl -fsSL https://example.com/uninstall.sh".to_string(),
+                format!("bash {}", cache.display())
+            ]
+        );
+        assert_eq!(fs::read_to_string(&cache)?, "echo uninstalled");
 
         Ok(())
     }
 
     #[rstest]
-    fn test_snapdpurge(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("snapdpurge");
-        File::create(&config_path)?;
-        let snapd_previously_installed = Command::new("dpkg")
-            .args(["--status", "snapd"])
-            .status()?
-            .success();
-
-        super::snapdpurge(&config_path)?;
+    fn test_snapdpurge(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Snapdpurge);
+        let state_dir = temp_dir.path().join("state");
+        let tracking = state_dir.join(TRACKING_FILE);
+        store.set(RhinoOption::Snapdpurge, true)?;
+        Tracked::update(&tracking, |tracked| {
+            tracked.holds.insert("snapd".to_string());
+            tracked.pins.push(Pin::new("snapd", -10));
+        })?;
+        let mut runner = RecordingRunner::default();
+
+        super::snapdpurge(&store, &state_dir, None)?.execute(&mut runner, &mut report)?;
         // Test that the config file is deleted
         assert!(!config_path.exists());
-        // Test that `snapd` and `gnome-software-plugin-snap` have been installed
-        assert!(Command::new("dpkg")
-            .args(["--status", "snapd", "gnome-software-plugin-snap"])
-            .status()?
-            .success());
-        // Test that `snapd` is unholded, i.e, it doesn't appear on `apt-mark showhold`
-        assert!(!String::from_utf8(
-            Command::new("sh")
-                .arg("apt-mark")
-                .arg("showhold")
-                .output()?
-                .stdout
-        )?
-        .contains("snapd"));
-
-        // Purge `snapd` and `gnome-software-plugin-snap` if previously not installed
-        // before test Don't run if the test is being run on a CI
-        if !snapd_previously_installed && var("CI").is_err() {
-            Command::new("sudo")
-                .args([
-                    "apt",
-                    "autopurge",
-                    "snapd",
-                    "gnome-software-plugin-snap",
-                    "--assume-yes",
-                ])
-                .status()?;
-        }
+        // Test that the tracked pin and hold are removed before `snapd` and
+        // `gnome-software-plugin-snap` are installed
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "rm -f /etc/apt/preferences.d/rhino-config-snapd.pref",
+                "apt-mark unhold snapd",
+                "apt install snapd gnome-software-plugin-snap -y",
+            ]
+        );
+        assert_eq!(Tracked::load(&tracking)?, Some(Tracked::default()));
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_snapdpurge_restore(
+        temp_dir: TempDir,
+        mut report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let state_dir = temp_dir.path().join("state");
+        let snap_path = temp_dir.path().join("snap");
+        store.set(RhinoOption::Snapdpurge, true)?;
+        fs::create_dir_all(snap_path.join("firefox"))?;
+        let backup = Backup::new(&snap_path, &store.backup_dir());
+        backup.create(&mut RecordingRunner::default())?;
+        fs::write(&backup.archive, "")?;
+        let mut runner = RecordingRunner::default();
+
+        super::snapdpurge(&store, &state_dir, Some(backup.clone()))?
+            .execute(&mut runner, &mut report)?;
+        // Test that the backup is restored once snapd has been reinstalled
+        assert_eq!(
+            runner.command_lines()[1..],
+            [
+                "apt install snapd gnome-software-plugin-snap -y".to_string(),
+                format!(
+                    "tar -xzf {} -C {}",
+                    backup.archive.display(),
+                    temp_dir.path().display()
+                )
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_snapdpurge_untracked_hold(
+        temp_dir: TempDir,
+        mut report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let state_dir = temp_dir.path().join("state");
+        store.set(RhinoOption::Snapdpurge, true)?;
+        Tracked::default().save(&state_dir.join(TRACKING_FILE))?;
+        let mut runner = RecordingRunner::default();
+
+        super::snapdpurge(&store, &state_dir, None)?.execute(&mut runner, &mut report)?;
+        // Test that a hold which was not added by rhino-config is kept
+        assert_eq!(
+            runner.command_lines(),
+            ["apt install snapd gnome-software-plugin-snap -y"]
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_snapdpurge_failure(
+        temp_dir: TempDir,
+        mut report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Snapdpurge);
+        let state_dir = temp_dir.path().join("state");
+        let journal_path = state_dir.join("snapdpurge-disable.failed");
+        store.set(RhinoOption::Snapdpurge, true)?;
+        let mut runner = RecordingRunner::default().fail_on("apt install");
+
+        // Test that a failing command aborts the operation
+        let error = super::snapdpurge(&store, &state_dir, None)?
+            .execute(&mut runner, &mut report)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unable to reinstall snapd!");
+        // Test that the hold from a purge which predates tracking is released,
+        // and restored once the reinstall failed
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "apt-mark unhold snapd",
+                "apt install snapd gnome-software-plugin-snap -y",
+                "apt-mark showhold",
+                "apt-mark hold snapd",
+            ]
+        );
+        // Test that snapdpurge stays enabled and the failure is recorded
+        assert!(config_path.exists());
+        assert!(journal_path.exists());
+
         Ok(())
     }
 }
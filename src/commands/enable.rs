@@ -1,120 +1,401 @@
-use std::fs::{self, File};
 use std::path::Path;
-use std::process::Command;
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{ensure, Result};
 use indoc::indoc;
 
-pub fn pacstall(config_path: &Path) -> Result<()> {
-    File::create(config_path).context("Unable to create pacstall config!")?;
-
-    println!(indoc!(
-        r#"
-        Pacstall has been enabled on the system, please check the
-        pacstall documentation on our website for information on how to
-        use this utility - please run "rhino-update" to update your system.
-        "#
-    ));
-    Ok(())
+use crate::apt::{Pin, SNAPD_PIN_PRIORITY, TRACKING_FILE};
+use crate::backup::Backup;
+use crate::config::Store;
+use crate::error::Error;
+use crate::helper::Request;
+use crate::options::RhinoOption;
+use crate::output::Action;
+use crate::pacstall::{INSTALLER_CACHE, STATE_FILE};
+use crate::plan::{Operation, Step};
+use crate::snaps::{Inventory, UNMATCHED_FILE};
+
+/// Enable Pacstall, which rhino-update then keeps updated.
+///
+/// If Pacstall is not installed yet it is installed with the installer from
+/// the config, if one is configured, and left to rhino-update otherwise.
+/// Whether it got installed is recorded in `state_dir`.
+pub fn pacstall(store: &Store, state_dir: &Path) -> Result<Operation> {
+    let config = store.load()?;
+    ensure!(
+        !config.is_enabled(RhinoOption::Pacstall),
+        Error::AlreadyEnabled("Pacstall is already enabled!".to_string())
+    );
+
+    Ok(Operation {
+        option: RhinoOption::Pacstall,
+        action: Action::Enable,
+        steps: vec![
+            Step::enable(store, RhinoOption::Pacstall),
+            Step::InstallPacstall {
+                installer: config.pacstall.installer,
+                cache: store.cache_dir().join(INSTALLER_CACHE),
+                state: state_dir.join(STATE_FILE),
+            },
+        ],
+        message: indoc!(
+            r#"
+            Pacstall has been enabled on the system, please check the
+            pacstall documentation on our website for information on how to
+            use this utility - please run "rhino-update" to update your system.
+            "#
+        )
+        .to_string(),
+        journal: None,
+    })
 }
 
-pub fn snapdpurge(config_path: &Path, snap_path: &Path) -> Result<()> {
-    File::create(config_path).context("Failed to create the snapdpurge config!")?;
-
-    ensure!(Command::new("sudo")
-        .args(["rm", "-rf", "/var/cache/snapd/"])
-        .status()
-        .context("Failed to remove snapd cache!")?
-        .success());
-
-    ensure!(Command::new("sudo")
-        .args([
-            "apt",
-            "autopurge",
-            "snapd",
-            "gnome-software-plugin-snap",
-            "-y",
-        ])
-        .status()
-        .context("Failed to remove snapd cache!")?
-        .success());
-
-    fs::remove_dir_all(&snap_path).context("Failed to remove snap directory!")?;
-
-    ensure!(Command::new("sudo")
-        .args([
-            "apt",
-            "install",
-            "flatpak",
-            "gnome-software-plugin-flatpak",
-            "-y",
-        ])
-        .status()
-        .context("Failed to install flatpak!")?
-        .success());
-
-    ensure!(Command::new("sudo")
-        .args([
-            "flatpak",
-            "remote-add",
-            "--if-not-exists",
-            "flathub",
-            "https://flathub.org/repo/flathub.flatpakrepo",
-        ])
-        .status()
-        .context("Failed to add flathub repository!")?
-        .success());
-
-    println!("Configuration updated, snapd has been removed from the system.");
-    Ok(())
+/// Flatpak remote added unless another one is configured.
+const FLATHUB_URL: &str = "https://flathub.org/repo/flathub.flatpakrepo";
+
+/// Purge snapd, block it from being reinstalled and replace it with Flatpak.
+///
+/// The option is only enabled once every other step has succeeded, so a failed
+/// purge can simply be run again. Steps which can be undone are rolled back on
+/// failure, and the failure is recorded in `state_dir`, which also tracks the
+/// apt hold and pin that are added.
+///
+/// The snaps in `inventory` are replaced with their Flatpaks, and those
+/// without a known replacement are listed in [`UNMATCHED_FILE`] in
+/// `state_dir`.
+///
+/// If `backup` is set, the snap directory is archived into the backup
+/// directory of `store` before anything is removed.
+pub fn snapdpurge(
+    store: &Store,
+    snap_path: &Path,
+    state_dir: &Path,
+    inventory: &Inventory,
+    backup: bool,
+) -> Result<Operation> {
+    let config = store.load()?;
+    ensure!(
+        !config.is_enabled(RhinoOption::Snapdpurge),
+        Error::AlreadyEnabled("Snapdpurge is already enabled!".to_string())
+    );
+    let remote = config
+        .snapdpurge
+        .flatpak_remote
+        .as_deref()
+        .unwrap_or(FLATHUB_URL);
+
+    let mut operation = Operation {
+        option: RhinoOption::Snapdpurge,
+        action: Action::Enable,
+        steps: Vec::new(),
+        message: "Configuration updated, snapd has been removed from the system.".to_string(),
+        journal: Some(state_dir.join("snapdpurge-enable.failed")),
+    };
+
+    if backup {
+        operation.steps.push(Step::Archive {
+            backup: Backup::new(snap_path, &store.backup_dir()),
+        });
+    }
+    operation.steps.extend([
+        Step::request(Request::ClearSnapdCache, "Failed to remove snapd cache!"),
+        Step::request(Request::PurgeSnapd, "Failed to remove snapd!")
+            .with_rollback(Request::InstallSnapd),
+        Step::Hold {
+            package: "snapd".to_string(),
+            tracking: state_dir.join(TRACKING_FILE),
+        },
+        Step::Pin {
+            pin: Pin::new("snapd", SNAPD_PIN_PRIORITY),
+            tracking: state_dir.join(TRACKING_FILE),
+        },
+        Step::request(Request::InstallFlatpak, "Failed to install flatpak!"),
+        Step::request(
+            Request::AddRemote {
+                name: "flathub".to_string(),
+                url: remote.to_string(),
+            },
+            "Failed to add flathub repository!",
+        ),
+    ]);
+
+    let flatpaks = inventory.flatpaks();
+    if !flatpaks.is_empty() {
+        operation.steps.push(
+            Step::request(
+                Request::InstallApps {
+                    remote: "flathub".to_string(),
+                    apps: flatpaks.into_iter().map(ToString::to_string).collect(),
+                },
+                "Failed to install the Flatpak replacements of your snaps!",
+            )
+            .with_progress("Installing the Flatpak replacements of your snaps..."),
+        );
+    }
+    if !inventory.unmatched.is_empty() {
+        operation.steps.push(Step::WriteFile {
+            path: state_dir.join(UNMATCHED_FILE),
+            contents: inventory.unmatched.join("\n") + "\n",
+        });
+    }
+
+    operation.steps.extend([
+        // The snap directory can only be restored from a backup, so only
+        // remove it once Flatpak is in place
+        Step::RemoveDir {
+            path: snap_path.to_path_buf(),
+        },
+        Step::enable(store, RhinoOption::Snapdpurge),
+    ]);
+    Ok(operation)
 }
 
 #[cfg(test)]
 mod tests {
     use std::error::Error;
-    use std::process::Command;
+    use std::fs;
 
+    use indoc::indoc;
     use rstest::*;
     use tempfile::{tempdir, TempDir};
 
+    use crate::apt::{Tracked, TRACKING_FILE};
+    use crate::backup::Backup;
+    use crate::config::Store;
+    use crate::options::RhinoOption;
+    use crate::output::{Format, Report};
+    use crate::pacstall::{InstallState, STATE_FILE};
+    use crate::plan::{Journal, Step};
+    use crate::runner::RecordingRunner;
+    use crate::snaps::{Inventory, UNMATCHED_FILE};
+
     #[fixture]
     fn temp_dir() -> TempDir { tempdir().unwrap() }
 
+    #[fixture]
+    fn report() -> Report { Report::new(Format::Text, "test") }
+
+    #[fixture]
+    fn runner() -> RecordingRunner { RecordingRunner::default() }
+
     #[rstest]
-    fn test_pacstall(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("pacstall");
+    fn test_pacstall(
+        temp_dir: TempDir,
+        mut report: Report,
+        mut runner: RecordingRunner,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Pacstall);
 
-        super::pacstall(&config_path)?;
+        let state_dir = temp_dir.path().join("state");
+
+        let operation = super::pacstall(&store, &state_dir)?;
+        // Test that nothing is run as root without an installer
+        assert!(!operation.steps.iter().any(Step::needs_root));
+        operation.execute(&mut runner, &mut report)?;
         // Test that the config file is created
         assert!(config_path.exists());
+        // Test that an existing install is detected
+        assert_eq!(
+            InstallState::load(&state_dir.join(STATE_FILE))?,
+            Some(InstallState::Installed)
+        );
+
+        // Test that an installer is run as root
+        let store = Store::new(&temp_dir.path().join("installer"));
+        fs::create_dir_all(&store.root)?;
+        fs::write(
+            &store.file,
+            indoc! {r#"
+                version = 1
+
+                [pacstall.installer]
+                source = "https://example.com/install.sh"
+            "#},
+        )?;
+        assert!(super::pacstall(&store, &state_dir)?
+            .steps
+            .iter()
+            .any(Step::needs_root));
 
         Ok(())
     }
 
     #[rstest]
-    fn test_snapdpurge(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("snapdpurge");
+    fn test_snapdpurge(
+        temp_dir: TempDir,
+        mut report: Report,
+        mut runner: RecordingRunner,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Snapdpurge);
+        let state_dir = temp_dir.path().join("state");
         let snap_dir = tempdir().unwrap();
         let snap_path = snap_dir.path();
 
-        super::snapdpurge(&config_path, snap_path)?;
+        let inventory = Inventory::new(vec!["firefox".to_string(), "hello".to_string()]);
+
+        super::snapdpurge(&store, snap_path, &state_dir, &inventory, true)?
+            .execute(&mut runner, &mut report)?;
         // Test that the config file is created
         assert!(config_path.exists());
         // Test that the snap_path has been deleted
         assert!(!snap_path.exists());
-        // Test that `snapd` and `gnome-software-plugin-snap` have been uninstalled
-        assert!(!Command::new("dpkg")
-            .args(["--status", "snapd", "gnome-software-plugin-snap"])
-            .status()?
-            .success());
-        // Test that `flatpak` has been installed
-        assert!(!Command::new("dpkg")
-            .args(["--status", "flatpak", "gnome-software-plugin-flatpak"])
-            .status()?
-            .success());
-        // Test that the flathub repository has been added to flatpak
-        let output = Command::new("flatpak").args(["remotes"]).output()?.stdout;
-        assert!(String::from_utf8(output)?.contains("flathub"));
+        // Test that snapd is purged, held and pinned, and flatpak is installed
+        // with flathub
+        assert_eq!(
+            runner.command_lines()[1..],
+            [
+                "rm -rf /var/cache/snapd/",
+                "apt autopurge snapd gnome-software-plugin-snap -y",
+                "apt-mark showhold",
+                "apt-mark hold snapd",
+                "install -m 644 /dev/stdin /etc/apt/preferences.d/rhino-config-snapd.pref",
+                "apt install flatpak gnome-software-plugin-flatpak -y",
+                "flatpak remote-add --if-not-exists flathub \
+                 https://flathub.org/repo/flathub.flatpakrepo",
+                "flatpak install -y --noninteractive flathub org.mozilla.firefox",
+            ]
+        );
+        // Test that the snap directory is backed up before anything is removed
+        let backup = Backup::latest(&store.backup_dir(), snap_path)?.unwrap();
+        assert!(runner.command_lines()[0]
+            .starts_with(&format!("tar -czf {}", backup.archive.display())));
+        // Test that the snaps without a replacement are listed
+        assert_eq!(
+            fs::read_to_string(state_dir.join(UNMATCHED_FILE))?,
+            "hello\n"
+        );
+        assert_eq!(
+            runner.commands[5].input.as_deref(),
+            Some("Package: snapd\nPin: release a=*\nPin-Priority: -10\n")
+        );
+        // Test that the hold and the pin are tracked
+        let tracked = Tracked::load(&state_dir.join(TRACKING_FILE))?.unwrap();
+        assert!(tracked.holds.contains("snapd"));
+        assert_eq!(tracked.pins_for("snapd").count(), 1);
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_snapdpurge_flatpak_remote(
+        temp_dir: TempDir,
+        mut runner: RecordingRunner,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        fs::write(
+            &store.file,
+            indoc!(
+                r#"
+                version = 1
+
+                [snapdpurge]
+                flatpak_remote = "https://example.com/repo.flatpakrepo"
+                "#
+            ),
+        )?;
+        let snap_dir = tempdir().unwrap();
+
+        super::snapdpurge(
+            &store,
+            snap_dir.path(),
+            &temp_dir.path().join("state"),
+            &Inventory::default(),
+            false,
+        )?
+        .execute(&mut runner, &mut Report::new(Format::Text, "test"))?;
+        // Test that the configured remote is added instead of Flathub
+        assert!(runner.command_lines().contains(
+            &"flatpak remote-add --if-not-exists flathub https://example.com/repo.flatpakrepo"
+                .to_string()
+        ));
+        // Test that enabling the option keeps the configured remote
+        assert_eq!(
+            store.load()?.snapdpurge.flatpak_remote.as_deref(),
+            Some("https://example.com/repo.flatpakrepo")
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_snapdpurge_already_held(
+        temp_dir: TempDir,
+        mut report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let state_dir = temp_dir.path().join("state");
+        let snap_dir = tempdir().unwrap();
+        let mut runner = RecordingRunner::default().with_stdout("apt-mark showhold", "snapd\n");
+
+        super::snapdpurge(
+            &store,
+            snap_dir.path(),
+            &state_dir,
+            &Inventory::default(),
+            false,
+        )?
+        .execute(&mut runner, &mut report)?;
+        // Test that a hold which was not added by rhino-config is not tracked
+        let tracked = Tracked::load(&state_dir.join(TRACKING_FILE))?.unwrap();
+        assert!(tracked.holds.is_empty());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_snapdpurge_rollback(
+        temp_dir: TempDir,
+        mut report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Snapdpurge);
+        let state_dir = temp_dir.path().join("state");
+        let journal_path = state_dir.join("snapdpurge-enable.failed");
+        let snap_dir = tempdir().unwrap();
+        let snap_path = snap_dir.path();
+        let mut runner = RecordingRunner::default().fail_on("apt install flatpak");
+
+        let operation =
+            super::snapdpurge(&store, snap_path, &state_dir, &Inventory::default(), false)?;
+        assert!(operation.execute(&mut runner, &mut report).is_err());
+        // Test that the pin and hold are removed and snapd is reinstalled after
+        // flatpak failed to install
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "rm -rf /var/cache/snapd/",
+                "apt autopurge snapd gnome-software-plugin-snap -y",
+                "apt-mark showhold",
+                "apt-mark hold snapd",
+                "install -m 644 /dev/stdin /etc/apt/preferences.d/rhino-config-snapd.pref",
+                "apt install flatpak gnome-software-plugin-flatpak -y",
+                "rm -f /etc/apt/preferences.d/rhino-config-snapd.pref",
+                "apt-mark unhold snapd",
+                "apt install snapd gnome-software-plugin-snap -y",
+            ]
+        );
+        assert_eq!(
+            Tracked::load(&state_dir.join(TRACKING_FILE))?,
+            Some(Tracked::default())
+        );
+        // Test that neither the marker nor the snap directory were touched
+        assert!(!config_path.exists());
+        assert!(snap_path.exists());
+        // Test that the failed step is recorded
+        let journal: Journal = serde_json::from_str(&fs::read_to_string(&journal_path)?)?;
+        assert_eq!(
+            journal.failed_step,
+            "run `apt install flatpak gnome-software-plugin-flatpak -y` as root"
+        );
+        assert_eq!(journal.rolled_back.len(), 3);
+
+        // Test that the purge can be run again and clears the journal
+        super::snapdpurge(&store, snap_path, &state_dir, &Inventory::default(), false)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
+        assert!(config_path.exists());
+        assert!(!snap_path.exists());
+        assert!(!journal_path.exists());
 
         Ok(())
     }
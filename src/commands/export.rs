@@ -0,0 +1,7 @@
+use anyhow::Result;
+
+use crate::config::Store;
+use crate::profile::Profile;
+
+/// The state of every option as a profile.
+pub fn export(store: &Store) -> Result<Profile> { Ok(Profile::of(&store.load()?)) }
@@ -0,0 +1,9 @@
+use anyhow::Result;
+
+use crate::config::Store;
+use crate::history::{self, Entry, Filter};
+
+/// The logged changes matching `filter`, oldest first.
+pub fn history(store: &Store, filter: &Filter) -> Result<Vec<Entry>> {
+    Ok(filter.apply(history::load(&store.history_file())?))
+}
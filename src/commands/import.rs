@@ -0,0 +1,201 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::commands::{disable, enable, switch_kernel};
+use crate::config::{Config, Store};
+use crate::options::{Kernel, RhinoOption};
+use crate::plan::Plan;
+use crate::profile::Profile;
+use crate::snaps::Inventory;
+
+/// An option whose state differs from the profile.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    pub option: &'static str,
+    pub current: String,
+    pub wanted: String,
+}
+
+/// The options of `config` which differ from `profile`.
+pub fn diff(config: &Config, profile: &Profile) -> Vec<Difference> {
+    let state = |enabled| if enabled { "enabled" } else { "disabled" }.to_string();
+    let kernel = |kernel: Option<Kernel>| {
+        kernel.map_or("stock".to_string(), |kernel| {
+            RhinoOption::Kernel(kernel).to_string()
+        })
+    };
+
+    let mut differences = Vec::new();
+    if config.kernel() != profile.kernel {
+        differences.push(Difference {
+            option: "kernel",
+            current: kernel(config.kernel()),
+            wanted: kernel(profile.kernel),
+        });
+    }
+    for (option, current, wanted) in [
+        ("snapdpurge", config.snapdpurge.enabled, profile.snapdpurge),
+        ("pacstall", config.pacstall.enabled, profile.pacstall),
+    ] {
+        if current != wanted {
+            differences.push(Difference {
+                option,
+                current: state(current),
+                wanted: state(wanted),
+            });
+        }
+    }
+    differences
+}
+
+/// Plan the operations that converge the system to `profile`.
+///
+/// Options which already match are left alone. If snapd is purged the snaps
+/// in `inventory` are replaced and `snap_path` is removed, after being backed
+/// up if `backup` is set, see [`enable::snapdpurge`].
+pub fn import(
+    store: &Store,
+    profile: &Profile,
+    snap_path: Option<&Path>,
+    state_dir: &Path,
+    inventory: &Inventory,
+    backup: bool,
+) -> Result<Plan> {
+    let config = store.load()?;
+    let mut plan = Plan::default();
+
+    match (config.kernel(), profile.kernel) {
+        (current, Some(wanted)) if current != Some(wanted) => {
+            plan.push(switch_kernel::enable(store, wanted, true)?)
+        },
+        (Some(current), None) => plan.push(switch_kernel::disable(store, current)?),
+        _ => {},
+    }
+
+    match (config.snapdpurge.enabled, profile.snapdpurge) {
+        (false, true) => plan.push(enable::snapdpurge(
+            store,
+            snap_path.context("Unable to find HOME environment variable!")?,
+            state_dir,
+            inventory,
+            backup,
+        )?),
+        (true, false) => plan.push(disable::snapdpurge(store, state_dir, None)?),
+        _ => {},
+    }
+
+    match (config.pacstall.enabled, profile.pacstall) {
+        (false, true) => plan.push(enable::pacstall(store, state_dir)?),
+        (true, false) => plan.push(disable::pacstall(store)?),
+        _ => {},
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::Difference;
+    use crate::config::Store;
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+    use crate::output::{Action, Format, Report};
+    use crate::profile::{Profile, PROFILE_VERSION};
+    use crate::runner::RecordingRunner;
+    use crate::snaps::Inventory;
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_import(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let state_dir = store.state_dir();
+        let edge = RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge));
+        store.set(edge, true)?;
+        let profile = Profile {
+            version: PROFILE_VERSION,
+            pacstall: true,
+            snapdpurge: false,
+            kernel: Some(Kernel::Liquorix),
+        };
+
+        // Test that only the options which differ are listed
+        assert_eq!(
+            super::diff(&store.load()?, &profile),
+            [
+                Difference {
+                    option: "kernel",
+                    current: "xanmod-edge".to_string(),
+                    wanted: "liquorix".to_string(),
+                },
+                Difference {
+                    option: "pacstall",
+                    current: "disabled".to_string(),
+                    wanted: "enabled".to_string(),
+                },
+            ]
+        );
+
+        let plan = super::import(
+            &store,
+            &profile,
+            None,
+            &state_dir,
+            &Inventory::default(),
+            false,
+        )?;
+        // Test that the kernel is switched and Pacstall is enabled
+        assert_eq!(
+            plan.operations
+                .iter()
+                .map(|operation| (operation.option, operation.action))
+                .collect::<Vec<_>>(),
+            [
+                (RhinoOption::Kernel(Kernel::Liquorix), Action::Enable),
+                (RhinoOption::Pacstall, Action::Enable),
+            ]
+        );
+        plan.execute(
+            &mut RecordingRunner::default(),
+            &mut Report::new(Format::Text, "test"),
+        )?;
+        assert_eq!(Profile::of(&store.load()?), profile);
+
+        // Test that importing the same profile again changes nothing
+        assert!(super::import(
+            &store,
+            &profile,
+            None,
+            &state_dir,
+            &Inventory::default(),
+            false
+        )?
+        .operations
+        .is_empty());
+
+        // Test that the stock kernel disables the selected one
+        let stock = Profile {
+            kernel: None,
+            ..profile
+        };
+        let plan = super::import(
+            &store,
+            &stock,
+            None,
+            &state_dir,
+            &Inventory::default(),
+            false,
+        )?;
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].action, Action::Disable);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::{Config, Store};
+use crate::options::RhinoOption;
+use crate::pacstall::{self, InstallState, STATE_FILE};
+use crate::runner::SystemRunner;
+use crate::system::{self, DPKG_STATUS_PATH};
+
+/// Whether the system actually matches what an option's marker asks for.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "state", content = "detail", rename_all = "snake_case")]
+pub enum SystemState {
+    InSync,
+    OutOfSync(String),
+    Unknown,
+}
+
+/// The current state of a single config option.
+#[derive(Debug, Serialize)]
+pub struct OptionStatus {
+    pub option: RhinoOption,
+    pub enabled: bool,
+    pub system: SystemState,
+}
+
+/// What is installed on the system, used to check options against reality.
+pub struct Probe {
+    /// Installed apt packages, or `None` if the dpkg database is unreadable.
+    pub packages: Option<HashSet<String>>,
+    pub pacstall_installed: bool,
+    /// What happened when Pacstall was last enabled.
+    pub pacstall_state: Option<InstallState>,
+}
+
+impl Probe {
+    /// Inspect the running system, and the state recorded in `state_dir`.
+    pub fn detect(state_dir: &Path) -> Self {
+        Probe {
+            packages: system::installed_packages(Path::new(DPKG_STATUS_PATH)).ok(),
+            // The same check `enable --pacstall` uses, so that both agree
+            pacstall_installed: pacstall::is_installed(&mut SystemRunner::default())
+                .unwrap_or_default(),
+            pacstall_state: InstallState::load(&state_dir.join(STATE_FILE))
+                .ok()
+                .flatten(),
+        }
+    }
+
+    fn package_installed(&self, name: &str) -> Option<bool> {
+        self.packages
+            .as_ref()
+            .map(|packages| packages.contains(name))
+    }
+}
+
+fn check(option: RhinoOption, enabled: bool, probe: &Probe) -> SystemState {
+    let mismatch = match option {
+        // A failed install rolls the option back, so it is reported either way
+        RhinoOption::Pacstall => match (enabled, probe.pacstall_installed) {
+            (_, false) if probe.pacstall_state == Some(InstallState::Failed) => {
+                Some("the Pacstall installer failed")
+            },
+            (true, false) => Some(r#"Pacstall is not installed yet, run "rhino-update""#),
+            (false, true) => Some("Pacstall is still installed"),
+            _ => None,
+        },
+        RhinoOption::Snapdpurge => match (enabled, probe.package_installed("snapd")) {
+            (_, None) => return SystemState::Unknown,
+            (true, Some(true)) => Some("snapd is still installed"),
+            (false, Some(false)) => Some("snapd is not installed"),
+            _ => None,
+        },
+        RhinoOption::Kernel(kernel) => match (
+            enabled,
+            probe
+                .packages
+                .as_ref()
+                .map(|packages| kernel.is_installed(packages)),
+        ) {
+            (_, None) => return SystemState::Unknown,
+            (true, Some(false)) => Some(r#"the kernel is not installed yet, run "rhino-update""#),
+            (false, Some(true)) => Some("the kernel is installed but not kept updated"),
+            _ => None,
+        },
+    };
+
+    match mismatch {
+        Some(message) => SystemState::OutOfSync(message.to_string()),
+        None => SystemState::InSync,
+    }
+}
+
+/// Collect the state of every known option.
+///
+/// # Arguments
+///
+/// * `config` - The config holding the enabled options.
+/// * `probe` - What is installed on the system.
+pub fn collect(config: &Config, probe: &Probe) -> Vec<OptionStatus> {
+    RhinoOption::all()
+        .into_iter()
+        .map(|option| {
+            let enabled = config.is_enabled(option);
+            OptionStatus {
+                option,
+                enabled,
+                system: check(option, enabled, probe),
+            }
+        })
+        .collect()
+}
+
+/// The state of every option of `store`, checked against the running system.
+pub fn status(store: &Store) -> Result<Vec<OptionStatus>> {
+    Ok(collect(&store.load()?, &Probe::detect(&store.state_dir())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::error::Error;
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::{Probe, SystemState};
+    use crate::commands::enable;
+    use crate::config::{Config, Store};
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+    use crate::output::{Format, Report};
+    use crate::pacstall::{InstallState, STATE_FILE};
+    use crate::runner::RecordingRunner;
+    use crate::script::sha256;
+
+    #[test]
+    fn test_collect() {
+        let mut config = Config::default();
+        config.set(RhinoOption::Snapdpurge, true);
+        config.set(
+            RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge)),
+            true,
+        );
+
+        let probe = Probe {
+            packages: Some(HashSet::from([
+                "snapd".to_string(),
+                "linux-xanmod-edge".to_string(),
+            ])),
+            pacstall_installed: false,
+            pacstall_state: None,
+        };
+        let statuses = super::collect(&config, &probe);
+
+        // Test that every option is listed
+        assert_eq!(statuses.len(), RhinoOption::all().len());
+
+        let find = |option| statuses.iter().find(|s| s.option == option).unwrap();
+
+        // Test that a purged snapd which is still installed is flagged
+        let snapdpurge = find(RhinoOption::Snapdpurge);
+        assert!(snapdpurge.enabled);
+        assert_eq!(
+            snapdpurge.system,
+            SystemState::OutOfSync("snapd is still installed".to_string())
+        );
+
+        // Test that an enabled and installed kernel is in sync
+        let edge = find(RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge)));
+        assert!(edge.enabled);
+        assert_eq!(edge.system, SystemState::InSync);
+
+        // Test that a disabled option which is not installed is in sync
+        let pacstall = find(RhinoOption::Pacstall);
+        assert!(!pacstall.enabled);
+        assert_eq!(pacstall.system, SystemState::InSync);
+    }
+
+    #[test]
+    fn test_collect_without_dpkg() {
+        let probe = Probe {
+            packages: None,
+            pacstall_installed: false,
+            pacstall_state: None,
+        };
+        let statuses = super::collect(&Config::default(), &probe);
+
+        // Test that package based checks are unknown without a dpkg database
+        let liquorix = statuses
+            .iter()
+            .find(|s| s.option == RhinoOption::Kernel(Kernel::Liquorix))
+            .unwrap();
+        assert_eq!(liquorix.system, SystemState::Unknown);
+    }
+
+    #[test]
+    fn test_collect_failed_pacstall() -> Result<(), Box<dyn Error>> {
+        let temp_dir = tempdir()?;
+        let store = Store::new(temp_dir.path());
+        let state_dir = temp_dir.path().join("state");
+        let installer = temp_dir.path().join("install.sh");
+        fs::write(&installer, "echo installed")?;
+        fs::write(
+            &store.file,
+            format!(
+                "version = 1\n\n[pacstall.installer]\nsource = {:?}\nsha256 = {:?}\n",
+                installer.to_str().unwrap(),
+                sha256(b"echo installed")
+            ),
+        )?;
+
+        // The installer runs, but does not leave `pacstall` behind
+        let mut runner = RecordingRunner::default().missing("pacstall");
+        let mut report = Report::new(Format::Text, "test");
+        assert!(enable::pacstall(&store, &state_dir)?
+            .execute(&mut runner, &mut report)
+            .is_err());
+
+        let probe = Probe {
+            packages: Some(HashSet::new()),
+            pacstall_installed: false,
+            pacstall_state: InstallState::load(&state_dir.join(STATE_FILE))?,
+        };
+        let statuses = super::collect(&store.load()?, &probe);
+        let pacstall = statuses
+            .iter()
+            .find(|s| s.option == RhinoOption::Pacstall)
+            .unwrap();
+
+        // Test that the failure is reported, although the option was rolled back
+        assert!(!pacstall.enabled);
+        assert_eq!(
+            pacstall.system,
+            SystemState::OutOfSync("the Pacstall installer failed".to_string())
+        );
+
+        Ok(())
+    }
+}
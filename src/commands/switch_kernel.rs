@@ -1,183 +1,452 @@
-use std::fs::{self, File};
+use std::collections::HashSet;
 use std::path::Path;
 
-use anyhow::{ensure, Context, Result};
-
-use crate::cli::XanmodVariants;
-
-macro_rules! create_config {
-    ($name: ident, $config_dir: ident) => {{
-        let config_path = $config_dir.join(concat!("xanmod-", stringify!($name)));
+use anyhow::{ensure, Result};
+use serde::Serialize;
+
+use crate::config::{Config, Store};
+use crate::error::Error;
+use crate::options::{Kernel, RhinoOption, XanmodVariant};
+use crate::output::Action;
+use crate::plan::{Operation, Step};
+use crate::system::{self, DPKG_STATUS_PATH, OSRELEASE_PATH};
+
+/// The steps that make `kernel` the selected kernel.
+///
+/// Fails with [`Error::Conflict`] if another kernel is enabled, unless
+/// `replace` is set, in which case it is disabled in the same operation.
+fn select(store: &Store, kernel: Kernel, replace: bool) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    if let Some(enabled) = store.load()?.kernel().filter(|&enabled| enabled != kernel) {
+        let option = RhinoOption::Kernel(enabled);
         ensure!(
-            !config_path.exists(),
-            format!(r#"The XanMod kernel variant: `{}` is already enabled! Run "rhino-update" to install it."#, stringify!($name))
+            replace,
+            Error::Conflict(format!(
+                "The `{}` kernel is already enabled ({})! Disable it first or pass `--replace` to \
+                 switch kernels.",
+                option,
+                store.marker(option).display()
+            ))
         );
-        File::create(config_path).context(format!("Failed to create the xanmod-{} config file!", stringify!($name)))?;
-        println!("{}", format!(r#"The XanMod kernel variant: `{}` is enabled! Run "rhino-update" to install it."#, stringify!($name)));
-
-    }};
-    ($name: ident, $config_dir: ident, $variants: ident) => {{
-        if $variants.$name {
-            create_config!($name, $config_dir);
-        }
-    }}
+        steps.push(Step::disable(store, option));
+    }
+    steps.push(Step::enable(store, RhinoOption::Kernel(kernel)));
+    Ok(steps)
 }
 
-macro_rules! remove_config {
-    ($name:ident, $config_dir:ident) => {{
-        let config_path = $config_dir.join(concat!("xanmod-", stringify!($name)));
-        ensure!(
-            config_path.exists(),
-            format!(
-                r#"The XanMod kernel variant: `{}` is already disabled!"#,
-                stringify!($name)
-            )
-        );
-        fs::remove_file(config_path).context(format!(
-            "Failed to remove the xanmod-{} config file!",
-            stringify!($name)
-        ))?;
-        println!(
-            "{}",
-            format!(
-                r#"The XanMod kernel variant: `{}` has been disabled!"#,
-                stringify!($name)
-            )
-        );
-    }};
-    ($name:ident, $config_dir:ident, $variants:ident) => {{
-        if $variants.$name {
-            remove_config!($name, $config_dir);
-        }
-    }};
-}
+/// Enable one of `variants` of the XanMod kernel, the stable one if none is
+/// given.
+///
+/// Fails with [`Error::Conflict`] if more than one variant is given.
+pub fn enable_xanmod(
+    store: &Store,
+    variants: &[XanmodVariant],
+    replace: bool,
+) -> Result<Operation> {
+    ensure!(
+        variants.len() <= 1,
+        Error::Conflict("Only one XanMod kernel variant can be enabled at a time!".to_string())
+    );
+    // Default to stable variant if no other variants are specfied
+    let variant = variants.first().copied().unwrap_or(XanmodVariant::Stable);
 
-pub fn enable_xanmod(config_dir: &Path, variants: &XanmodVariants) -> Result<()> {
-    create_config!(stable, config_dir, variants);
-    create_config!(edge, config_dir, variants);
-    create_config!(lts, config_dir, variants);
-    create_config!(realtime, config_dir, variants);
-    create_config!(realtime_edge, config_dir, variants);
-    create_config!(tasktype, config_dir, variants);
+    let kernel = Kernel::Xanmod(variant);
+    ensure!(
+        !store.load()?.is_enabled(RhinoOption::Kernel(kernel)),
+        Error::AlreadyEnabled(format!(
+            r#"The XanMod kernel variant: `{}` is already enabled! Run "rhino-update" to install it."#,
+            variant.name()
+        ))
+    );
+    Ok(Operation {
+        option: RhinoOption::Kernel(kernel),
+        action: Action::Enable,
+        steps: select(store, kernel, replace)?,
+        message: format!(
+            r#"The XanMod kernel variant: `{}` is enabled! Run "rhino-update" to install it."#,
+            variant.name()
+        ),
+        journal: None,
+    })
+}
 
-    // Default to stable variant if no other variants are specfied
-    if variants == &XanmodVariants::default() {
-        create_config!(stable, config_dir);
-    }
-    Ok(())
+/// Enable the Liquorix kernel.
+pub fn enable_liquorix(store: &Store, replace: bool) -> Result<Operation> {
+    ensure!(
+        !store
+            .load()?
+            .is_enabled(RhinoOption::Kernel(Kernel::Liquorix)),
+        Error::AlreadyEnabled(
+            r#"The Liquorix kernel is already enabled! Run "rhino-update" to install it."#
+                .to_string()
+        )
+    );
+    Ok(Operation {
+        option: RhinoOption::Kernel(Kernel::Liquorix),
+        action: Action::Enable,
+        steps: select(store, Kernel::Liquorix, replace)?,
+        message:
+            r#"The Liquorix kernel has been enabled - please run "rhino-update" to install it."#
+                .to_string(),
+        journal: None,
+    })
 }
 
-pub fn enable_liquorix(config_path: &Path) -> Result<()> {
+/// Enable the Libre kernel.
+pub fn enable_libre(store: &Store, replace: bool) -> Result<Operation> {
     ensure!(
-        !config_path.exists(),
-        r#"The Liquorix kernel is already enabled! Run "rhino-update" to install it."#
+        !store.load()?.is_enabled(RhinoOption::Kernel(Kernel::Libre)),
+        Error::AlreadyEnabled(
+            r#"The Libre kernel is already enabled! Run "rhino-update" to install it."#.to_string()
+        )
     );
-    File::create(config_path).context("Failed to create the liquorix config file!")?;
-    println!(r#"The Liquorix kernel has been enabled - please run "rhino-update" to install it."#);
-    Ok(())
+    Ok(Operation {
+        option: RhinoOption::Kernel(Kernel::Libre),
+        action: Action::Enable,
+        steps: select(store, Kernel::Libre, replace)?,
+        message: r#"The Libre kernel has been enabled - please run "rhino-update" to install it."#
+            .to_string(),
+        journal: None,
+    })
 }
 
-pub fn enable_libre(config_path: &Path) -> Result<()> {
+/// Enable the mainline kernel.
+pub fn enable_mainline(store: &Store, replace: bool) -> Result<Operation> {
     ensure!(
-        !config_path.exists(),
-        r#"The Libre kernel is already enabled! Run "rhino-update" to install it."#
+        !store
+            .load()?
+            .is_enabled(RhinoOption::Kernel(Kernel::Mainline)),
+        Error::AlreadyEnabled(
+            r#"The mainline kernel is already enabled! Run "rhino-update" to install it."#
+                .to_string()
+        )
     );
-    File::create(config_path).context("Failed to create the libre config file!")?;
-    println!(r#"The Libre kernel has been enabled - please run "rhino-update" to install it."#);
-    Ok(())
+    Ok(Operation {
+        option: RhinoOption::Kernel(Kernel::Mainline),
+        action: Action::Enable,
+        steps: select(store, Kernel::Mainline, replace)?,
+        message:
+            r#"The mainline kernel has been enabled - please run "rhino-update" to install it."#
+                .to_string(),
+        journal: None,
+    })
 }
 
-pub fn disable_xanmod(config_dir: &Path, variants: &XanmodVariants) -> Result<()> {
-    remove_config!(stable, config_dir, variants);
-    remove_config!(edge, config_dir, variants);
-    remove_config!(lts, config_dir, variants);
-    remove_config!(realtime, config_dir, variants);
-    remove_config!(realtime_edge, config_dir, variants);
-    remove_config!(tasktype, config_dir, variants);
+fn disable_variant(store: &Store, config: &Config, variant: XanmodVariant) -> Result<Operation> {
+    let option = RhinoOption::Kernel(Kernel::Xanmod(variant));
+    ensure!(
+        config.is_enabled(option),
+        Error::AlreadyDisabled(format!(
+            r#"The XanMod kernel variant: `{}` is already disabled!"#,
+            variant.name()
+        ))
+    );
+    Ok(Operation {
+        option,
+        action: Action::Disable,
+        steps: vec![Step::disable(store, option)],
+        message: format!(
+            r#"The XanMod kernel variant: `{}` has been disabled!"#,
+            variant.name()
+        ),
+        journal: None,
+    })
+}
 
+/// Disable `variants` of the XanMod kernel, the stable one if none is given.
+pub fn disable_xanmod(store: &Store, variants: &[XanmodVariant]) -> Result<Vec<Operation>> {
+    let config = store.load()?;
     // Default to stable variant if no other variants are specfied
-    if variants == &XanmodVariants::default() {
-        remove_config!(stable, config_dir);
-    }
-    Ok(())
+    let variants = if variants.is_empty() {
+        &[XanmodVariant::Stable]
+    } else {
+        variants
+    };
+
+    variants
+        .iter()
+        .map(|&variant| disable_variant(store, &config, variant))
+        .collect()
 }
 
-pub fn disable_liquorix(config_path: &Path) -> Result<()> {
+/// Disable the Liquorix kernel.
+pub fn disable_liquorix(store: &Store) -> Result<Operation> {
+    let option = RhinoOption::Kernel(Kernel::Liquorix);
     ensure!(
-        config_path.exists(),
-        r#"The Liquorix kernel is already disabled!"#
+        store.load()?.is_enabled(option),
+        Error::AlreadyDisabled(r#"The Liquorix kernel is already disabled!"#.to_string())
     );
-    fs::remove_file(config_path).context("Failed to remove the liquorix config file!")?;
-    println!(r#"The Liquorix kernel has been disabled."#);
-    Ok(())
+    Ok(Operation {
+        option,
+        action: Action::Disable,
+        steps: vec![Step::disable(store, option)],
+        message: r#"The Liquorix kernel has been disabled."#.to_string(),
+        journal: None,
+    })
 }
 
-pub fn disable_libre(config_path: &Path) -> Result<()> {
+/// Disable the Libre kernel.
+pub fn disable_libre(store: &Store) -> Result<Operation> {
+    let option = RhinoOption::Kernel(Kernel::Libre);
     ensure!(
-        config_path.exists(),
-        r#"The Libre kernel is already disabled!"#
+        store.load()?.is_enabled(option),
+        Error::AlreadyDisabled(r#"The Libre kernel is already disabled!"#.to_string())
     );
-    fs::remove_file(config_path).context("Failed to remove the libre config file!")?;
-    println!(r#"The Libre kernel has been disabled."#);
-    Ok(())
+    Ok(Operation {
+        option,
+        action: Action::Disable,
+        steps: vec![Step::disable(store, option)],
+        message: r#"The Libre kernel has been disabled."#.to_string(),
+        journal: None,
+    })
+}
+
+/// Disable the mainline kernel.
+pub fn disable_mainline(store: &Store) -> Result<Operation> {
+    let option = RhinoOption::Kernel(Kernel::Mainline);
+    ensure!(
+        store.load()?.is_enabled(option),
+        Error::AlreadyDisabled(r#"The mainline kernel is already disabled!"#.to_string())
+    );
+    Ok(Operation {
+        option,
+        action: Action::Disable,
+        steps: vec![Step::disable(store, option)],
+        message: r#"The mainline kernel has been disabled."#.to_string(),
+        journal: None,
+    })
+}
+
+/// Enable `kernel`, whichever one it is.
+pub fn enable(store: &Store, kernel: Kernel, replace: bool) -> Result<Operation> {
+    match kernel {
+        Kernel::Xanmod(variant) => enable_xanmod(store, &[variant], replace),
+        Kernel::Liquorix => enable_liquorix(store, replace),
+        Kernel::Libre => enable_libre(store, replace),
+        Kernel::Mainline => enable_mainline(store, replace),
+    }
+}
+
+/// Disable `kernel`, whichever one it is.
+pub fn disable(store: &Store, kernel: Kernel) -> Result<Operation> {
+    match kernel {
+        Kernel::Xanmod(variant) => disable_variant(store, &store.load()?, variant),
+        Kernel::Liquorix => disable_liquorix(store),
+        Kernel::Libre => disable_libre(store),
+        Kernel::Mainline => disable_mainline(store),
+    }
+}
+
+/// The state of a single alternate kernel.
+#[derive(Debug, Serialize)]
+pub struct KernelStatus {
+    pub option: RhinoOption,
+    pub family: &'static str,
+    pub enabled: bool,
+    /// `None` if the dpkg database could not be read.
+    pub installed: Option<bool>,
+    pub running: bool,
+}
+
+/// Collect the state of every alternate kernel.
+///
+/// # Arguments
+///
+/// * `config` - The config, holding the selected kernel.
+/// * `packages` - The installed packages, if known.
+/// * `release` - The release of the running kernel, if known.
+pub fn collect(
+    config: &Config,
+    packages: Option<&HashSet<String>>,
+    release: Option<&str>,
+) -> Vec<KernelStatus> {
+    Kernel::all()
+        .into_iter()
+        .map(|kernel| {
+            let installed = packages.map(|packages| kernel.is_installed(packages));
+            KernelStatus {
+                option: RhinoOption::Kernel(kernel),
+                family: kernel.family(),
+                enabled: config.is_enabled(RhinoOption::Kernel(kernel)),
+                installed,
+                // Several XanMod variants share a release, so only count
+                // those that are installed
+                running: release.is_some_and(|release| kernel.matches_release(release))
+                    && installed != Some(false),
+            }
+        })
+        .collect()
+}
+
+/// The alternate kernels, along with the running one.
+#[derive(Debug, Serialize)]
+pub struct KernelList {
+    /// The release of the running kernel, if known.
+    pub running_release: Option<String>,
+    pub kernels: Vec<KernelStatus>,
+}
+
+/// The state of every alternate kernel on the running system.
+pub fn list(store: &Store) -> Result<KernelList> {
+    let config = store.load()?;
+    let packages = system::installed_packages(Path::new(DPKG_STATUS_PATH)).ok();
+    let running_release = system::running_release(Path::new(OSRELEASE_PATH)).ok();
+    Ok(KernelList {
+        kernels: collect(&config, packages.as_ref(), running_release.as_deref()),
+        running_release,
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
     use std::error::Error;
-    use std::fs::File;
+    use std::fs::{self, File};
 
     use rstest::*;
     use tempfile::{tempdir, TempDir};
 
-    use crate::cli::XanmodVariants;
+    use crate::config::{Config, Store};
+    use crate::error::ErrorKind;
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+    use crate::output::{Action, Format, Report};
+    use crate::runner::RecordingRunner;
 
     #[fixture]
     fn temp_dir() -> TempDir { tempdir().unwrap() }
 
+    #[fixture]
+    fn report() -> Report { Report::new(Format::Text, "test") }
+
     #[rstest]
-    fn test_enable_xanmod(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_dir = temp_dir.path();
-        let variants = XanmodVariants {
-            stable: true,
-            edge: true,
-            lts: true,
-            realtime: true,
-            realtime_edge: true,
-            tasktype: true,
-        };
+    fn test_enable_xanmod(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let variants = [XanmodVariant::Edge];
 
         // Test that the config file is created
-        super::enable_xanmod(&config_dir, &variants)?;
-        assert!(config_dir.join("xanmod-stable").exists());
-        assert!(config_dir.join("xanmod-edge").exists());
-        assert!(config_dir.join("xanmod-lts").exists());
-        assert!(config_dir.join("xanmod-realtime").exists());
-        assert!(config_dir.join("xanmod-realtime_edge").exists());
-        assert!(config_dir.join("xanmod-tasktype").exists());
+        super::enable_xanmod(&store, &variants, false)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
+        assert!(store.marker_dir.join("xanmod-edge").exists());
 
         // Test that it errors out if the config file is already present
         assert_eq!(
-            super::enable_xanmod(&config_dir, &variants)
+            super::enable_xanmod(&store, &variants, false)
                 .unwrap_err()
                 .to_string(),
-            r#"The XanMod kernel variant: `stable` is already enabled! Run "rhino-update" to install it."#
+            r#"The XanMod kernel variant: `edge` is already enabled! Run "rhino-update" to install it."#
+        );
+
+        // Test that only a single variant can be enabled
+        let variants = [XanmodVariant::Stable, XanmodVariant::Lts];
+        let error = super::enable_xanmod(&store, &variants, true).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Conflict);
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_collect() {
+        let mut config = Config::default();
+        config.set(
+            RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Lts)),
+            true,
+        );
+        let packages = HashSet::from([
+            "linux-xanmod-lts".to_string(),
+            "linux-xanmod-rt".to_string(),
+            "linux-image-unsigned-6.0.2-060002-generic".to_string(),
+        ]);
+
+        let statuses = super::collect(&config, Some(&packages), Some("5.15.74-x64v1-xanmod1"));
+        let find = |kernel| {
+            statuses
+                .iter()
+                .find(|s| s.option == RhinoOption::Kernel(kernel))
+                .unwrap()
+        };
+
+        // Test that every kernel is listed
+        assert_eq!(statuses.len(), Kernel::all().len());
+
+        // Test that the enabled, installed and running kernel is reported
+        let lts = find(Kernel::Xanmod(XanmodVariant::Lts));
+        assert!(lts.enabled);
+        assert_eq!(lts.installed, Some(true));
+        assert!(lts.running);
+
+        // Test that an installed realtime variant is not taken to be running
+        let realtime = find(Kernel::Xanmod(XanmodVariant::Realtime));
+        assert!(!realtime.enabled);
+        assert_eq!(realtime.installed, Some(true));
+        assert!(!realtime.running);
+
+        // Test that mainline builds are found by their image packages
+        let mainline = find(Kernel::Mainline);
+        assert_eq!(mainline.installed, Some(true));
+        assert!(!mainline.running);
+
+        // Test that installation is unknown without a dpkg database
+        let statuses = super::collect(&config, None, Some("6.0.2-060002-generic"));
+        let mainline = statuses
+            .iter()
+            .find(|s| s.option == RhinoOption::Kernel(Kernel::Mainline))
+            .unwrap();
+        assert_eq!(mainline.installed, None);
+        assert!(mainline.running);
+    }
+
+    #[rstest]
+    fn test_enable_conflict(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let lts = store.marker(RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Lts)));
+        // Test against a legacy marker, from before the config document
+        fs::create_dir_all(&store.marker_dir)?;
+        File::create(&lts)?;
+
+        // Test that it errors out naming the marker of the enabled kernel
+        let error = super::enable_liquorix(&store, false).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Conflict);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "The `xanmod-lts` kernel is already enabled ({})! Disable it first or pass \
+                 `--replace` to switch kernels.",
+                lts.display()
+            )
+        );
+
+        // Test that the enabled kernel is replaced in a single operation
+        super::enable_liquorix(&store, true)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
+        assert_eq!(store.load()?.kernel(), Some(Kernel::Liquorix));
+        assert!(!lts.exists());
+
+        super::enable_xanmod(&store, &[], true)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
+        assert_eq!(
+            store.load()?.kernel(),
+            Some(Kernel::Xanmod(XanmodVariant::Stable))
         );
 
         Ok(())
     }
 
     #[rstest]
-    fn test_enable_liquorix(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("liquorix");
+    fn test_enable_liquorix(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Kernel(Kernel::Liquorix));
 
         // Test that the config file is created
-        super::enable_liquorix(&config_path)?;
+        super::enable_liquorix(&store, false)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
         assert!(config_path.exists());
+        // Test that the change is reported
+        assert_eq!(report.changes()[0].option, "liquorix");
+        assert_eq!(report.changes()[0].action, Action::Enable);
 
         // Test that it errors out if the config file is already present
         assert_eq!(
-            super::enable_liquorix(&config_path)
+            super::enable_liquorix(&store, false)
                 .unwrap_err()
                 .to_string(),
             r#"The Liquorix kernel is already enabled! Run "rhino-update" to install it."#
@@ -187,18 +456,18 @@ mod tests {
     }
 
     #[rstest]
-    fn test_enable_libre(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("libre");
+    fn test_enable_libre(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Kernel(Kernel::Libre));
 
         // Test that the config file is created
-        super::enable_libre(&config_path)?;
+        super::enable_libre(&store, false)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
         assert!(config_path.exists());
 
         // Test that it errors out if the config file is already present
         assert_eq!(
-            super::enable_libre(&config_path)
-                .unwrap_err()
-                .to_string(),
+            super::enable_libre(&store, false).unwrap_err().to_string(),
             r#"The Libre kernel is already enabled! Run "rhino-update" to install it."#
         );
 
@@ -206,85 +475,102 @@ mod tests {
     }
 
     #[rstest]
-    fn test_disable_xanmod(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_dir = temp_dir.path();
-        let variants = XanmodVariants {
-            stable: true,
-            edge: true,
-            lts: true,
-            realtime: true,
-            realtime_edge: true,
-            tasktype: true,
-        };
+    fn test_enable_mainline(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Kernel(Kernel::Mainline));
 
-        let xanmod_stable = &config_dir.join("xanmod-stable");
-        let xanmod_edge = &config_dir.join("xanmod-edge");
-        let xanmod_lts = &config_dir.join("xanmod-lts");
-        let xanmod_realtime = &config_dir.join("xanmod-realtime");
-        let xanmod_realtime_edge = &config_dir.join("xanmod-realtime_edge");
-        let xanmod_tasktype = &config_dir.join("xanmod-tasktype");
+        // Test that the config file is created
+        super::enable_mainline(&store, false)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
+        assert!(config_path.exists());
+        assert_eq!(report.changes()[0].option, "mainline");
 
-        File::create(xanmod_stable)?;
-        File::create(xanmod_edge)?;
-        File::create(xanmod_lts)?;
-        File::create(xanmod_realtime)?;
-        File::create(xanmod_realtime_edge)?;
-        File::create(xanmod_tasktype)?;
+        // Test that it errors out if the config file is already present
+        assert_eq!(
+            super::enable_mainline(&store, false)
+                .unwrap_err()
+                .to_string(),
+            r#"The mainline kernel is already enabled! Run "rhino-update" to install it."#
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_disable_xanmod(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let realtime = RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Realtime));
+        let variants = [XanmodVariant::Realtime];
+        store.set(realtime, true)?;
 
         // Test that the config file is deleted
-        super::disable_xanmod(&config_dir, &variants)?;
-        assert!(!xanmod_stable.exists());
-        assert!(!xanmod_edge.exists());
-        assert!(!xanmod_lts.exists());
-        assert!(!xanmod_realtime.exists());
-        assert!(!xanmod_realtime_edge.exists());
-        assert!(!xanmod_tasktype.exists());
+        for operation in super::disable_xanmod(&store, &variants)? {
+            operation.execute(&mut RecordingRunner::default(), &mut report)?;
+        }
+        assert!(!store.marker(realtime).exists());
+        assert_eq!(store.load()?.kernel(), None);
 
         // Test that it errors out if the config file is not present
         assert_eq!(
-            super::disable_xanmod(&config_dir, &variants)
+            super::disable_xanmod(&store, &variants)
                 .unwrap_err()
                 .to_string(),
-            r#"The XanMod kernel variant: `stable` is already disabled!"#
+            r#"The XanMod kernel variant: `realtime` is already disabled!"#
         );
         Ok(())
     }
 
     #[rstest]
-    fn test_disable_liquorix(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("liquorix");
-        File::create(&config_path)?;
+    fn test_disable_liquorix(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Kernel(Kernel::Liquorix));
+        store.set(RhinoOption::Kernel(Kernel::Liquorix), true)?;
 
         // Test that the config file is deleted
-        super::disable_liquorix(&config_path)?;
+        super::disable_liquorix(&store)?.execute(&mut RecordingRunner::default(), &mut report)?;
         assert!(!config_path.exists());
 
         // Test that it errors out if the config file is not present
         assert_eq!(
-            super::disable_liquorix(&config_path)
-                .unwrap_err()
-                .to_string(),
+            super::disable_liquorix(&store).unwrap_err().to_string(),
             r#"The Liquorix kernel is already disabled!"#
         );
         Ok(())
     }
 
     #[rstest]
-    fn test_disable_libre(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
-        let config_path = temp_dir.path().join("libre");
-        File::create(&config_path)?;
+    fn test_disable_libre(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Kernel(Kernel::Libre));
+        store.set(RhinoOption::Kernel(Kernel::Libre), true)?;
 
         // Test that the config file is deleted
-        super::disable_libre(&config_path)?;
+        super::disable_libre(&store)?.execute(&mut RecordingRunner::default(), &mut report)?;
         assert!(!config_path.exists());
 
         // Test that it errors out if the config file is not present
         assert_eq!(
-            super::disable_libre(&config_path)
-                .unwrap_err()
-                .to_string(),
+            super::disable_libre(&store).unwrap_err().to_string(),
             r#"The Libre kernel is already disabled!"#
         );
         Ok(())
     }
+
+    #[rstest]
+    fn test_disable_mainline(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let config_path = store.marker(RhinoOption::Kernel(Kernel::Mainline));
+        store.set(RhinoOption::Kernel(Kernel::Mainline), true)?;
+
+        // Test that the config file is deleted
+        super::disable_mainline(&store)?.execute(&mut RecordingRunner::default(), &mut report)?;
+        assert!(!config_path.exists());
+
+        // Test that it errors out if the config file is not present
+        assert_eq!(
+            super::disable_mainline(&store).unwrap_err().to_string(),
+            r#"The mainline kernel is already disabled!"#
+        );
+        Ok(())
+    }
 }
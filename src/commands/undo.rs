@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::backup::Backup;
+use crate::commands::{disable, enable, switch_kernel};
+use crate::config::Store;
+use crate::error::Error;
+use crate::history::{self, Entry, OptionState, Undo};
+use crate::options::RhinoOption;
+use crate::plan::Plan;
+
+/// The most recent change that left anything applied, which is the one
+/// undone.
+///
+/// Changes whose every operation failed are skipped, as a failed operation is
+/// rolled back when it fails.
+pub fn last(store: &Store) -> Result<Entry> {
+    history::load(&store.history_file())?
+        .into_iter()
+        .rev()
+        .find(|entry| entry.applied().next().is_some())
+        .context("There is nothing to undo, no changes have been recorded!")
+}
+
+/// Whether undoing `entry` reinstalls snapd, after which the snap directory
+/// can be restored from a backup.
+pub fn reinstalls_snapd(entry: &Entry) -> bool {
+    let snapdpurge = RhinoOption::Snapdpurge.to_string();
+    entry
+        .applied()
+        .filter_map(|operation| match &operation.undo {
+            Some(Undo::Revert { options }) => Some(options),
+            _ => None,
+        })
+        .flatten()
+        .any(|state| state.option == snapdpurge && !state.enabled)
+}
+
+/// Plan the inverse of `entry`, reverting the operations that succeeded last
+/// to first.
+///
+/// If snapd is reinstalled, the snap directory is restored from `restore`.
+/// Fails with [`Error::Irreversible`] if any of the operations can not be
+/// reverted.
+pub fn undo(
+    store: &Store,
+    state_dir: &Path,
+    entry: &Entry,
+    mut restore: Option<Backup>,
+) -> Result<Plan> {
+    let mut reverts = Vec::new();
+    for operation in entry.applied().rev() {
+        match &operation.undo {
+            Some(Undo::Revert { options }) => reverts.push(options),
+            Some(Undo::Irreversible { reason }) => bail!(Error::Irreversible(format!(
+                "`{}` can not be undone! {}",
+                entry.args.join(" "),
+                reason
+            ))),
+            None => bail!(Error::Irreversible(format!(
+                "`{}` was recorded before changes could be undone, so it can not be undone!",
+                entry.args.join(" ")
+            ))),
+        }
+    }
+
+    let mut plan = Plan::default();
+    for options in reverts {
+        let states = options
+            .iter()
+            .map(|OptionState { option, enabled }| Ok((option.parse()?, *enabled)))
+            .collect::<Result<Vec<(RhinoOption, bool)>>>()?;
+
+        // Kernels are switched back as a whole, so that a kernel which was
+        // replaced is enabled again in place of its replacement
+        let kernels = states
+            .iter()
+            .filter_map(|&(option, enabled)| match option {
+                RhinoOption::Kernel(kernel) => Some((kernel, enabled)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match kernels.iter().find(|(_, enabled)| *enabled) {
+            Some(&(kernel, _)) => plan.push(switch_kernel::enable(store, kernel, true)?),
+            None => {
+                for &(kernel, _) in &kernels {
+                    plan.push(switch_kernel::disable(store, kernel)?);
+                }
+            },
+        }
+
+        for (option, enabled) in states {
+            match (option, enabled) {
+                (RhinoOption::Kernel(_), _) => {},
+                (RhinoOption::Pacstall, true) => plan.push(enable::pacstall(store, state_dir)?),
+                (RhinoOption::Pacstall, false) => plan.push(disable::pacstall(store)?),
+                (RhinoOption::Snapdpurge, false) => {
+                    plan.push(disable::snapdpurge(store, state_dir, restore.take())?)
+                },
+                (RhinoOption::Snapdpurge, true) => bail!(Error::Irreversible(format!(
+                    "`{}` can not be undone, as that would purge snapd again!",
+                    entry.args.join(" ")
+                ))),
+            }
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use crate::commands::{disable, enable, switch_kernel};
+    use crate::config::Store;
+    use crate::error::ErrorKind;
+    use crate::history::{self, Entry};
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+    use crate::output::{Action, Format, Report};
+    use crate::plan::{Operation, Plan, Step};
+    use crate::runner::RecordingRunner;
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[fixture]
+    fn report() -> Report { Report::new(Format::Text, "test") }
+
+    /// Execute `operation` and record it in the history of `store`.
+    fn apply(store: &Store, operation: Operation, args: &str, report: &mut Report) -> Plan {
+        let mut plan = Plan::default();
+        plan.push(operation);
+        let mut completed = Vec::new();
+        let result = plan.execute_tracked(&mut RecordingRunner::default(), report, &mut completed);
+        let entry = Entry::new(
+            None,
+            args.split(' ').map(ToString::to_string).collect(),
+            &plan,
+            &completed,
+            Vec::new(),
+            &result,
+        );
+        history::record(&store.history_file(), &entry).unwrap();
+        plan
+    }
+
+    #[rstest]
+    fn test_undo_kernel(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let state_dir = store.state_dir();
+        let edge = RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge));
+        let liquorix = RhinoOption::Kernel(Kernel::Liquorix);
+
+        // Test that there is nothing to undo without a history
+        assert!(super::last(&store).is_err());
+
+        apply(
+            &store,
+            switch_kernel::enable_xanmod(&store, &[XanmodVariant::Edge], false)?,
+            "rhino-config switch-kernel enable xanmod --edge",
+            &mut report,
+        );
+        apply(
+            &store,
+            switch_kernel::enable_liquorix(&store, true)?,
+            "rhino-config switch-kernel enable liquorix --replace",
+            &mut report,
+        );
+
+        // Test that a replaced kernel is enabled again
+        let entry = super::last(&store)?;
+        let plan = apply(
+            &store,
+            super::undo(&store, &state_dir, &entry, None)?
+                .operations
+                .remove(0),
+            "rhino-config undo",
+            &mut report,
+        );
+        assert_eq!(plan.operations[0].action, Action::Enable);
+        let config = store.load()?;
+        assert!(config.is_enabled(edge));
+        assert!(!config.is_enabled(liquorix));
+
+        // Test that undoing the undo reverts it
+        let entry = super::last(&store)?;
+        super::undo(&store, &state_dir, &entry, None)?
+            .execute(&mut RecordingRunner::default(), &mut report)?;
+        assert!(store.load()?.is_enabled(liquorix));
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_undo_partial(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let liquorix = RhinoOption::Kernel(Kernel::Liquorix);
+
+        let mut plan = Plan::default();
+        plan.push(switch_kernel::enable_liquorix(&store, false)?);
+        plan.push(Operation {
+            option: RhinoOption::Pacstall,
+            action: Action::Enable,
+            steps: vec![
+                Step::enable(&store, RhinoOption::Pacstall),
+                Step::run("false", Vec::<String>::new(), "Failed!"),
+            ],
+            message: String::new(),
+            journal: None,
+        });
+        let mut completed = Vec::new();
+        let result = plan.execute_tracked(
+            &mut RecordingRunner::default().fail_on("false"),
+            &mut report,
+            &mut completed,
+        );
+        assert!(result.is_err());
+        let entry = Entry::new(None, Vec::new(), &plan, &completed, Vec::new(), &result);
+        history::record(&store.history_file(), &entry)?;
+
+        // Test that the operations which succeeded before one failed are undone
+        let entry = super::last(&store)?;
+        assert!(!entry.success);
+        let plan = super::undo(&store, &store.state_dir(), &entry, None)?;
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].option, liquorix);
+        assert_eq!(plan.operations[0].action, Action::Disable);
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_undo_snapdpurge(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let state_dir = store.state_dir();
+        let snap_dir = tempdir()?;
+
+        apply(
+            &store,
+            enable::snapdpurge(
+                &store,
+                snap_dir.path(),
+                &state_dir,
+                &Default::default(),
+                false,
+            )?,
+            "rhino-config enable --snapdpurge",
+            &mut report,
+        );
+
+        // Test that undoing a snap purge reinstalls snapd
+        let entry = super::last(&store)?;
+        assert!(super::reinstalls_snapd(&entry));
+        let plan = super::undo(&store, &state_dir, &entry, None)?;
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].option, RhinoOption::Snapdpurge);
+        assert_eq!(plan.operations[0].action, Action::Disable);
+
+        // Test that reinstalling snapd can not be undone
+        apply(
+            &store,
+            disable::snapdpurge(&store, &state_dir, None)?,
+            "rhino-config disable --snapdpurge",
+            &mut report,
+        );
+        let entry = super::last(&store)?;
+        assert!(!super::reinstalls_snapd(&entry));
+        let error = super::undo(&store, &state_dir, &entry, None).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Irreversible);
+        assert!(error
+            .to_string()
+            .starts_with("`rhino-config disable --snapdpurge` can not be undone!"));
+
+        Ok(())
+    }
+}
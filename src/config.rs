@@ -0,0 +1,689 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use toml::value::{Table, Value};
+
+use crate::escalation::Backend;
+use crate::history::HISTORY_FILE;
+use crate::options::{Kernel, RhinoOption};
+use crate::script::Script;
+
+/// Name of the config document inside the rhino directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Name of the directory holding the legacy marker files.
+pub const MARKER_DIR: &str = "config";
+
+/// Name of the directory holding state, such as journals of failed attempts.
+pub const STATE_DIR: &str = "state";
+
+/// Name of the directory holding cached downloads.
+pub const CACHE_DIR: &str = "cache";
+
+/// Name of the directory holding backups, such as archives of `~/snap`.
+pub const BACKUP_DIR: &str = "backups";
+
+/// The system-wide config document, overridden by the per-user one.
+///
+/// rhino-update only reads the markers, which are written for the options
+/// enabled in here whenever rhino-config changes the config of a user.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/rhino/config.toml";
+
+/// Version of the config document written by this release.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// The config, `~/.rhino/config.toml` on top of [`SYSTEM_CONFIG_PATH`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Config {
+    pub version: u32,
+    #[serde(default)]
+    pub pacstall: Pacstall,
+    #[serde(default)]
+    pub snapdpurge: Snapdpurge,
+    #[serde(default)]
+    pub kernel: KernelConfig,
+    #[serde(default)]
+    pub privilege: Privilege,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Pacstall {
+    pub enabled: bool,
+    /// The script run to install Pacstall when it is enabled, if it is not
+    /// installed yet. Left to rhino-update if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installer: Option<Script>,
+    /// The script run to uninstall Pacstall when it is disabled, which has to
+    /// be pinned to its checksum. Pacstall is left installed if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uninstaller: Option<Script>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Snapdpurge {
+    pub enabled: bool,
+    /// Flatpak remote added in place of snapd, Flathub if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flatpak_remote: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KernelConfig {
+    /// The selected alternate kernel, `stock` for the Ubuntu kernel.
+    #[serde(with = "selected")]
+    pub selected: Option<Kernel>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Privilege {
+    /// The program privileged commands are run through, the first of sudo,
+    /// doas and pkexec that is installed if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+}
+
+/// Serializes the selected kernel, writing `stock` when there is none so that
+/// a layer can explicitly deselect a kernel selected by a lower layer.
+pub(crate) mod selected {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::options::Kernel;
+
+    pub const STOCK: &str = "stock";
+
+    pub fn serialize<S: Serializer>(
+        kernel: &Option<Kernel>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match kernel {
+            Some(kernel) => kernel.serialize(serializer),
+            None => serializer.serialize_str(STOCK),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Kernel>, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name == STOCK {
+            return Ok(None);
+        }
+        Kernel::deserialize(serde::de::value::StringDeserializer::new(name)).map(Some)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            pacstall: Pacstall::default(),
+            snapdpurge: Snapdpurge::default(),
+            kernel: KernelConfig::default(),
+            privilege: Privilege::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Whether `option` is enabled.
+    pub fn is_enabled(&self, option: RhinoOption) -> bool {
+        match option {
+            RhinoOption::Pacstall => self.pacstall.enabled,
+            RhinoOption::Snapdpurge => self.snapdpurge.enabled,
+            RhinoOption::Kernel(kernel) => self.kernel() == Some(kernel),
+        }
+    }
+
+    /// The selected alternate kernel.
+    pub fn kernel(&self) -> Option<Kernel> { self.kernel.selected }
+
+    /// Enable or disable `option`.
+    ///
+    /// Enabling a kernel replaces the selected one, disabling a kernel that is
+    /// not selected does nothing.
+    pub fn set(&mut self, option: RhinoOption, enabled: bool) {
+        match option {
+            RhinoOption::Pacstall => self.pacstall.enabled = enabled,
+            RhinoOption::Snapdpurge => self.snapdpurge.enabled = enabled,
+            RhinoOption::Kernel(kernel) => {
+                if enabled {
+                    self.kernel.selected = Some(kernel);
+                } else if !enabled && self.kernel() == Some(kernel) {
+                    self.kernel.selected = None;
+                }
+            },
+        }
+    }
+}
+
+/// A layer of the config, a value set in a higher layer overrides the lower
+/// ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    Default,
+    System,
+    User,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Layer::Default => "default",
+            Layer::System => "system",
+            Layer::User => "user",
+        })
+    }
+}
+
+/// The key of the value holding the state of `option`.
+fn key(option: RhinoOption) -> [&'static str; 2] {
+    match option {
+        RhinoOption::Pacstall => ["pacstall", "enabled"],
+        RhinoOption::Snapdpurge => ["snapdpurge", "enabled"],
+        RhinoOption::Kernel(_) => ["kernel", "selected"],
+    }
+}
+
+/// A single config document, holding only the values it sets.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+struct Document(#[serde(serialize_with = "toml::ser::tables_last")] Table);
+
+impl Document {
+    /// Read a document, `None` if it does not exist.
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read {}!", path.display()))?;
+        let table: Table =
+            toml::from_str(&contents).context(format!("Failed to parse {}!", path.display()))?;
+        let version = table.get("version").and_then(Value::as_integer);
+        match version {
+            Some(version) if version > i64::from(CONFIG_VERSION) => bail!(
+                "{} uses version {} of the config format, which is newer than this release of \
+                 rhino-config supports!",
+                path.display(),
+                version
+            ),
+            Some(_) => Ok(Some(Document(table))),
+            None => bail!("{} does not declare its `version`!", path.display()),
+        }
+    }
+
+    /// A document enabling the options whose marker is in `marker_dir`.
+    ///
+    /// Only one kernel can be selected, if markers for several kernels are
+    /// present the first one in [`Kernel::all`] order is kept.
+    fn from_markers(marker_dir: &Path) -> Self {
+        let mut document = Document::default();
+        for option in RhinoOption::all() {
+            if marker_dir.join(option.marker()).exists() && document.get(key(option)).is_none() {
+                document.set(key(option), enabled_value(option));
+            }
+        }
+        document
+    }
+
+    fn get(&self, [section, name]: [&str; 2]) -> Option<&Value> {
+        self.0.get(section)?.as_table()?.get(name)
+    }
+
+    fn set(&mut self, [section, name]: [&str; 2], value: Value) {
+        self.0
+            .insert("version".to_string(), Value::Integer(CONFIG_VERSION.into()));
+        let section = self
+            .0
+            .entry(section)
+            .or_insert_with(|| Value::Table(Table::new()));
+        if let Value::Table(section) = section {
+            section.insert(name.to_string(), value);
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create {}!", parent.display()))?;
+        }
+        fs::write(path, toml::to_string(self)?)
+            .context(format!("Failed to write {}!", path.display()))
+    }
+}
+
+/// Load the config from the system-wide document at `path` alone, ignoring
+/// the per-user ones.
+pub fn load_system(path: &Path) -> Result<Config> {
+    parse(Document::read(path)?.unwrap_or_default().0, path)
+}
+
+/// Parse the merged `table`, naming `path` if it is invalid.
+fn parse(mut table: Table, path: &Path) -> Result<Config> {
+    table.insert("version".to_string(), Value::Integer(CONFIG_VERSION.into()));
+    Value::Table(table)
+        .try_into()
+        .context(format!("Failed to parse {}!", path.display()))
+}
+
+/// The value of the key of `option` when it is enabled.
+fn enabled_value(option: RhinoOption) -> Value {
+    match option {
+        RhinoOption::Kernel(_) => Value::String(option.marker()),
+        _ => Value::Boolean(true),
+    }
+}
+
+/// Merge `layer` into `base`, values in `layer` win.
+fn merge(base: &mut Table, layer: &Table) {
+    for (key, value) in layer {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            },
+        }
+    }
+}
+
+/// Where the config is stored.
+///
+/// The per-user document is the source of truth for changes. The marker files
+/// rhino-update reads are still written alongside it until it learns the new
+/// format.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Store {
+    pub root: PathBuf,
+    pub file: PathBuf,
+    pub marker_dir: PathBuf,
+    /// The system-wide document, if any.
+    pub system_file: Option<PathBuf>,
+}
+
+impl Store {
+    /// The store inside the rhino directory, usually `~/.rhino`.
+    pub fn new(root: &Path) -> Self {
+        Store {
+            root: root.to_path_buf(),
+            file: root.join(CONFIG_FILE),
+            marker_dir: root.join(MARKER_DIR),
+            system_file: None,
+        }
+    }
+
+    /// Layer the store on top of the system-wide document at `path`.
+    pub fn with_system_file(mut self, path: &Path) -> Self {
+        self.system_file = Some(path.to_path_buf());
+        self
+    }
+
+    /// Find the rhino directory.
+    ///
+    /// In order of preference this is `config_dir`, given by `--config-dir` or
+    /// `RHINO_CONFIG_DIR`, `$XDG_CONFIG_HOME/rhino` and `~/.rhino`. The XDG
+    /// location is skipped if it does not exist yet while `~/.rhino` does, so
+    /// that existing setups keep working.
+    ///
+    /// Unless `config_dir` is given, the markers are kept in `~/.rhino/config`
+    /// as long as the home directory is known. An explicit directory keeps
+    /// its own markers, so that it never touches the real home directory.
+    pub fn locate(
+        config_dir: Option<&Path>,
+        xdg_config_home: Option<&Path>,
+        home: Option<&Path>,
+    ) -> Result<Self> {
+        let legacy = home.map(|home| home.join(".rhino"));
+        let xdg = xdg_config_home
+            .filter(|path| path.is_absolute())
+            .map(|path| path.join("rhino"));
+        let mut store = match (config_dir, xdg, &legacy) {
+            (Some(config_dir), ..) => Store::new(config_dir),
+            (None, Some(xdg), Some(legacy)) if !xdg.exists() && legacy.exists() => {
+                Store::new(legacy)
+            },
+            (None, Some(xdg), _) => Store::new(&xdg),
+            (None, None, Some(legacy)) => Store::new(legacy),
+            (None, None, None) => bail!(
+                "Unable to find the config directory! Pass `--config-dir` or set \
+                 RHINO_CONFIG_DIR, XDG_CONFIG_HOME or HOME."
+            ),
+        };
+        // rhino-update only ever reads the markers from ~/.rhino/config
+        if let (None, Some(legacy)) = (config_dir, legacy) {
+            store.marker_dir = legacy.join(MARKER_DIR);
+        }
+        Ok(store)
+    }
+
+    /// Write the per-user document from the markers unless it exists, and
+    /// return the markers of the kernels that were left out, which are
+    /// removed.
+    ///
+    /// Only one kernel can be selected, see [`Document::from_markers`].
+    pub fn migrate(&self) -> Result<Vec<PathBuf>> {
+        if self.file.exists() {
+            return Ok(Vec::new());
+        }
+        let document = Document::from_markers(&self.marker_dir);
+        if document.0.is_empty() {
+            return Ok(Vec::new());
+        }
+        document.write(&self.file)?;
+
+        let config = self.load()?;
+        let removed = Kernel::all()
+            .into_iter()
+            .map(RhinoOption::Kernel)
+            .filter(|&option| !config.is_enabled(option) && self.marker(option).exists())
+            .map(|option| self.marker(option))
+            .collect();
+        self.write_markers(&config)?;
+        Ok(removed)
+    }
+
+    /// The directory holding state, such as journals of failed attempts.
+    pub fn state_dir(&self) -> PathBuf { self.root.join(STATE_DIR) }
+
+    /// The directory holding cached downloads.
+    pub fn cache_dir(&self) -> PathBuf { self.root.join(CACHE_DIR) }
+
+    /// The log of the changes that have been made.
+    pub fn history_file(&self) -> PathBuf { self.root.join(HISTORY_FILE) }
+
+    /// The directory holding backups.
+    pub fn backup_dir(&self) -> PathBuf { self.root.join(BACKUP_DIR) }
+
+    /// The per-user document, migrated from the marker files if it does not
+    /// exist yet.
+    fn user(&self) -> Result<Document> {
+        Ok(Document::read(&self.file)?.unwrap_or_else(|| Document::from_markers(&self.marker_dir)))
+    }
+
+    fn system(&self) -> Result<Option<Document>> {
+        match &self.system_file {
+            Some(path) => Document::read(path),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the config, with the per-user document on top of the system-wide
+    /// one.
+    pub fn load(&self) -> Result<Config> {
+        let mut table = Table::new();
+        if let (Some(path), Some(system)) = (&self.system_file, self.system()?) {
+            // Parsed on its own first, so that its errors name the right file
+            parse(system.0.clone(), path)?;
+            merge(&mut table, &system.0);
+        }
+        merge(&mut table, &self.user()?.0);
+        parse(table, &self.file)
+    }
+
+    /// The layer the state of `option` comes from.
+    pub fn layer(&self, option: RhinoOption) -> Result<Layer> {
+        if self.user()?.get(key(option)).is_some() {
+            return Ok(Layer::User);
+        }
+        match self.system()? {
+            Some(system) if system.get(key(option)).is_some() => Ok(Layer::System),
+            _ => Ok(Layer::Default),
+        }
+    }
+
+    /// The legacy marker file of `option`.
+    pub fn marker(&self, option: RhinoOption) -> PathBuf { self.marker_dir.join(option.marker()) }
+
+    /// Enable or disable `option` in the per-user document and its marker
+    /// file.
+    pub fn set(&self, option: RhinoOption, enabled: bool) -> Result<()> {
+        let mut config = self.load()?;
+        config.set(option, enabled);
+
+        // Only the changed value is written, everything else is still
+        // inherited from the system-wide document
+        let [section, name] = key(option);
+        let mut user = self.user()?;
+        user.set(
+            key(option),
+            Value::try_from(&config)?[section][name].clone(),
+        );
+        user.write(&self.file)?;
+        self.write_markers(&config)
+    }
+
+    /// Create the markers of the options enabled in `config` and remove the
+    /// others, so that options enabled in the system-wide document reach
+    /// rhino-update as well.
+    fn write_markers(&self, config: &Config) -> Result<()> {
+        for option in RhinoOption::all() {
+            let marker = self.marker(option);
+            if config.is_enabled(option) {
+                fs::create_dir_all(&self.marker_dir)
+                    .context(format!("Failed to create {}!", self.marker_dir.display()))?;
+                File::create(&marker).context(format!(
+                    "Failed to create the config file {}!",
+                    marker.display()
+                ))?;
+            } else if marker.exists() {
+                fs::remove_file(&marker).context(format!(
+                    "Failed to remove the config file {}!",
+                    marker.display()
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs::{self, File};
+
+    use indoc::indoc;
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::Store;
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_migration(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        fs::create_dir_all(&store.marker_dir)?;
+        File::create(store.marker_dir.join("snapdpurge"))?;
+        File::create(store.marker_dir.join("mainline"))?;
+
+        // Test that existing markers are picked up without a config document
+        let config = store.load()?;
+        assert!(config.is_enabled(RhinoOption::Snapdpurge));
+        assert!(!config.is_enabled(RhinoOption::Pacstall));
+        assert_eq!(config.kernel(), Some(Kernel::Mainline));
+
+        // Test that the migrated options are kept once the document is written
+        store.set(RhinoOption::Pacstall, true)?;
+        assert!(store.file.exists());
+        let config = store.load()?;
+        assert!(config.is_enabled(RhinoOption::Snapdpurge));
+        assert!(config.is_enabled(RhinoOption::Pacstall));
+        // Test that the legacy marker is written as well
+        assert!(store.marker_dir.join("pacstall").exists());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_migrate(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let stable = RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Stable));
+        let liquorix = RhinoOption::Kernel(Kernel::Liquorix);
+
+        // Test that there is nothing to migrate without markers
+        assert!(store.migrate()?.is_empty());
+        assert!(!store.file.exists());
+
+        fs::create_dir_all(&store.marker_dir)?;
+        File::create(store.marker(stable))?;
+        File::create(store.marker(liquorix))?;
+        // Test that the markers of the kernels left out are removed
+        assert_eq!(store.migrate()?, [store.marker(liquorix)]);
+        assert!(store.file.exists());
+        assert!(store.marker(stable).exists());
+        assert!(!store.marker(liquorix).exists());
+        assert_eq!(
+            store.load()?.kernel(),
+            Some(Kernel::Xanmod(XanmodVariant::Stable))
+        );
+
+        // Test that an existing document is left alone
+        File::create(store.marker(liquorix))?;
+        assert!(store.migrate()?.is_empty());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_set_kernel(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let edge = RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge));
+
+        store.set(edge, true)?;
+        store.set(RhinoOption::Kernel(Kernel::Libre), false)?;
+        // Test that disabling a kernel that is not selected keeps the selection
+        assert!(store.load()?.is_enabled(edge));
+        assert_eq!(
+            fs::read_to_string(&store.file)?,
+            indoc!(
+                r#"
+                version = 1
+
+                [kernel]
+                selected = "xanmod-edge"
+                "#
+            )
+        );
+
+        store.set(edge, false)?;
+        assert_eq!(store.load()?.kernel(), None);
+        assert!(!store.marker_dir.join("xanmod-edge").exists());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_locate(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let home = temp_dir.path().join("home");
+        let xdg = temp_dir.path().join("xdg");
+        let explicit = temp_dir.path().join("explicit");
+
+        // Test that the explicit directory wins
+        let store = Store::locate(Some(&explicit), Some(&xdg), Some(&home))?;
+        assert_eq!(store.root, explicit);
+        // Test that the explicit directory keeps its own markers
+        assert_eq!(store.marker_dir, explicit.join("config"));
+        assert_eq!(
+            Store::locate(Some(&explicit), None, None)?.marker_dir,
+            explicit.join("config")
+        );
+        // Test that an explicit directory never touches the home directory
+        fs::create_dir_all(&store.marker_dir)?;
+        fs::write(store.marker(RhinoOption::Pacstall), "")?;
+        store.migrate()?;
+        store.set(RhinoOption::Snapdpurge, true)?;
+        assert!(store.load()?.is_enabled(RhinoOption::Pacstall));
+        assert!(!home.exists());
+
+        // Test that the XDG location is preferred for new setups while the
+        // markers stay where rhino-update reads them
+        let store = Store::locate(None, Some(&xdg), Some(&home))?;
+        assert_eq!(store.root, xdg.join("rhino"));
+        assert_eq!(store.marker_dir, home.join(".rhino/config"));
+
+        // Test that an existing ~/.rhino keeps being used
+        fs::create_dir_all(home.join(".rhino"))?;
+        assert_eq!(
+            Store::locate(None, Some(&xdg), Some(&home))?.root,
+            home.join(".rhino")
+        );
+        fs::create_dir_all(xdg.join("rhino"))?;
+        assert_eq!(
+            Store::locate(None, Some(&xdg), Some(&home))?.root,
+            xdg.join("rhino")
+        );
+
+        assert!(Store::locate(None, None, None).is_err());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_system_markers(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let system_file = temp_dir.path().join("system.toml");
+        fs::write(&system_file, "version = 1\n\n[pacstall]\nenabled = true\n")?;
+        let store = Store::new(&temp_dir.path().join("user")).with_system_file(&system_file);
+
+        // Test that options enabled system-wide get a marker once the config
+        // is changed
+        store.set(RhinoOption::Snapdpurge, true)?;
+        assert!(store.marker(RhinoOption::Pacstall).exists());
+        assert!(store.marker(RhinoOption::Snapdpurge).exists());
+        store.set(RhinoOption::Pacstall, false)?;
+        assert!(!store.marker(RhinoOption::Pacstall).exists());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_invalid_layer(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let system_file = temp_dir.path().join("system.toml");
+        fs::write(
+            &system_file,
+            "version = 1\n\n[pacstall]\nenabled = \"yes\"\n",
+        )?;
+        let store = Store::new(temp_dir.path()).with_system_file(&system_file);
+
+        // Test that errors name the layer they come from
+        let error = format!("{:#}", store.load().unwrap_err());
+        assert!(
+            error.contains(&system_file.display().to_string()),
+            "{}",
+            error
+        );
+
+        fs::write(&system_file, "version = 1\n")?;
+        fs::write(
+            &store.file,
+            "version = 1\n\n[pacstall]\nenabled = \"yes\"\n",
+        )?;
+        let error = format!("{:#}", store.load().unwrap_err());
+        assert!(
+            error.contains(&store.file.display().to_string()),
+            "{}",
+            error
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_newer_version(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        fs::write(&store.file, "version = 2\n")?;
+
+        // Test that documents from a newer release are refused
+        assert!(store.load().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::process::ExitStatus;
+use std::{fmt, io};
+
+use serde::Serialize;
+
+/// Errors that scripts wrapping rhino-config may need to tell apart.
+///
+/// Each error maps onto an [`ErrorKind`], which in turn has a stable exit
+/// code.
+#[derive(Debug)]
+pub enum Error {
+    /// The option was already enabled.
+    AlreadyEnabled(String),
+
+    /// The option was already disabled.
+    AlreadyDisabled(String),
+
+    /// The option can not be enabled alongside an option that is enabled.
+    Conflict(String),
+
+    /// An external command exited unsuccessfully.
+    CommandFailed { command: String, status: ExitStatus },
+
+    /// The change can not be undone.
+    Irreversible(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AlreadyEnabled(message)
+            | Error::AlreadyDisabled(message)
+            | Error::Conflict(message)
+            | Error::Irreversible(message) => f.write_str(message),
+            Error::CommandFailed { command, status } => {
+                write!(f, "`{}` failed with {}", command, status)
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The class of a failure, used for exit codes and machine-readable output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    AlreadyEnabled,
+    AlreadyDisabled,
+    CommandFailed,
+    Io,
+    Conflict,
+    Irreversible,
+    Other,
+}
+
+impl ErrorKind {
+    /// Classify an error by the first [`Error`] or [`io::Error`] in its
+    /// chain.
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(error) = cause.downcast_ref::<Error>() {
+                return match error {
+                    Error::AlreadyEnabled(_) => ErrorKind::AlreadyEnabled,
+                    Error::AlreadyDisabled(_) => ErrorKind::AlreadyDisabled,
+                    Error::Conflict(_) => ErrorKind::Conflict,
+                    Error::CommandFailed { .. } => ErrorKind::CommandFailed,
+                    Error::Irreversible(_) => ErrorKind::Irreversible,
+                };
+            }
+            if cause.is::<io::Error>() {
+                return ErrorKind::Io;
+            }
+        }
+        ErrorKind::Other
+    }
+
+    /// The exit code rhino-config terminates with for this kind of failure.
+    ///
+    /// `2` is left out as it is used by clap for usage errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::AlreadyEnabled => 3,
+            ErrorKind::AlreadyDisabled => 4,
+            ErrorKind::CommandFailed => 5,
+            ErrorKind::Io => 6,
+            ErrorKind::Conflict => 7,
+            ErrorKind::Irreversible => 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use anyhow::{anyhow, Context};
+
+    use super::{Error, ErrorKind};
+
+    #[test]
+    fn test_error_kind() {
+        let already_enabled = anyhow!(Error::AlreadyEnabled("enabled".to_string()));
+        assert_eq!(ErrorKind::of(&already_enabled), ErrorKind::AlreadyEnabled);
+        assert_eq!(already_enabled.to_string(), "enabled");
+
+        // Test that the kind is found through added context
+        let command_failed = Err::<(), _>(Error::CommandFailed {
+            command: "apt install snapd".to_string(),
+            status: ExitStatus::from_raw(1 << 8),
+        })
+        .context("Unable to reinstall snapd!")
+        .unwrap_err();
+        assert_eq!(ErrorKind::of(&command_failed), ErrorKind::CommandFailed);
+        assert_eq!(ErrorKind::of(&command_failed).exit_code(), 5);
+
+        let io = Err::<(), _>(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("Failed to create the liquorix config file!")
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&io), ErrorKind::Io);
+
+        assert_eq!(ErrorKind::of(&anyhow!("anything else")), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use std::process::{ExitStatus, Output};
+use std::{fmt, io};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::helper::{self, HELPER};
+use crate::runner::{CommandRunner, ExternalCommand};
+use crate::{system, user};
+
+/// A program that runs commands as root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Sudo,
+    Doas,
+    Pkexec,
+}
+
+impl Backend {
+    /// Every backend, in the order they are looked for.
+    pub fn all() -> [Backend; 3] { [Backend::Sudo, Backend::Doas, Backend::Pkexec] }
+
+    /// The program of the backend.
+    pub fn program(self) -> &'static str {
+        match self {
+            Backend::Sudo => "sudo",
+            Backend::Doas => "doas",
+            Backend::Pkexec => "pkexec",
+        }
+    }
+
+    /// The command asking for the password once, so that later commands run
+    /// without prompting.
+    ///
+    /// pkexec has no credential cache, so it asks for every command.
+    fn authentication(self) -> Option<ExternalCommand> {
+        match self {
+            Backend::Sudo => Some(ExternalCommand::new("sudo", ["-v"])),
+            Backend::Doas => Some(ExternalCommand::new("doas", ["true"])),
+            Backend::Pkexec => None,
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.pad(self.program()) }
+}
+
+/// How privileged commands are run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Escalation {
+    /// rhino-config is already root, so commands run as they are.
+    None,
+    /// Helper requests are sent to `helper` through `backend`.
+    Backend { backend: Backend, helper: PathBuf },
+}
+
+impl Escalation {
+    /// Pick the escalation, preferring the `configured` backend over the
+    /// first of the `available` ones.
+    ///
+    /// Escalating needs the `helper`, as nothing else is ever run as root.
+    fn select(
+        is_root: bool,
+        configured: Option<Backend>,
+        available: &[Backend],
+        helper: Option<PathBuf>,
+    ) -> Result<Self> {
+        if is_root {
+            return Ok(Escalation::None);
+        }
+        let backend = match configured {
+            Some(backend) if available.contains(&backend) => backend,
+            Some(backend) => bail!(
+                "The configured privilege backend, {}, is not installed!",
+                backend
+            ),
+            None => *available.first().context(
+                "Changing the system needs root, but none of sudo, doas or pkexec is installed!",
+            )?,
+        };
+        let helper = helper.context(format!(
+            "Changing the system needs {} to run as root, but it is not installed next to \
+             rhino-config!",
+            HELPER
+        ))?;
+        Ok(Escalation::Backend { backend, helper })
+    }
+
+    /// Work out the escalation for this process, using the `configured`
+    /// backend if set.
+    pub fn detect(configured: Option<Backend>) -> Result<Self> {
+        let available = Backend::all()
+            .into_iter()
+            .filter(|backend| system::find_executable(backend.program()).is_some())
+            .collect::<Vec<_>>();
+        Escalation::select(user::is_root(), configured, &available, helper::locate())
+    }
+
+    /// `command` as it is run.
+    ///
+    /// When escalating, the helper request the command carries out is sent to
+    /// the helper, and other commands run as the user.
+    pub fn wrap(&self, command: &ExternalCommand) -> ExternalCommand {
+        match (self, &command.request) {
+            (Escalation::Backend { backend, helper }, Some(request)) => ExternalCommand {
+                request: Some(request.clone()),
+                ..ExternalCommand::new(backend.program(), [helper.to_string_lossy()])
+                    .with_input(serde_json::to_string(request).unwrap_or_default())
+            },
+            _ => command.clone(),
+        }
+    }
+
+    /// Ask for the password up front, so that a long change is not
+    /// interrupted halfway through.
+    pub fn authenticate(&self, runner: &mut dyn CommandRunner) -> Result<()> {
+        match self {
+            Escalation::Backend { backend, .. } => match backend.authentication() {
+                Some(command) => runner
+                    .run(&command)
+                    .context(format!("Failed to authenticate with {}!", backend)),
+                None => Ok(()),
+            },
+            Escalation::None => Ok(()),
+        }
+    }
+}
+
+/// Runs helper requests through an [`Escalation`].
+pub struct EscalatingRunner<'a> {
+    runner: &'a mut dyn CommandRunner,
+    escalation: Escalation,
+}
+
+impl<'a> EscalatingRunner<'a> {
+    pub fn new(runner: &'a mut dyn CommandRunner, escalation: Escalation) -> Self {
+        EscalatingRunner { runner, escalation }
+    }
+}
+
+impl CommandRunner for EscalatingRunner<'_> {
+    fn status(&mut self, command: &ExternalCommand) -> io::Result<ExitStatus> {
+        self.runner.status(&self.escalation.wrap(command))
+    }
+
+    fn output(&mut self, command: &ExternalCommand) -> io::Result<Output> {
+        self.runner.output(&self.escalation.wrap(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::path::PathBuf;
+
+    use super::{Backend, EscalatingRunner, Escalation};
+    use crate::helper::Request;
+    use crate::runner::{CommandRunner, ExternalCommand, RecordingRunner};
+
+    fn helper() -> PathBuf { PathBuf::from("/usr/bin/rhino-config-helper") }
+
+    fn backend(backend: Backend) -> Escalation {
+        Escalation::Backend {
+            backend,
+            helper: helper(),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let all = Backend::all();
+        let select = |is_root, configured, available: &[Backend]| {
+            Escalation::select(is_root, configured, available, Some(helper()))
+        };
+
+        // Test that root does not escalate again
+        assert_eq!(
+            Escalation::select(true, Some(Backend::Doas), &[], None).unwrap(),
+            Escalation::None
+        );
+        assert_eq!(select(false, None, &all).unwrap(), backend(Backend::Sudo));
+        assert_eq!(
+            select(false, Some(Backend::Pkexec), &all).unwrap(),
+            backend(Backend::Pkexec)
+        );
+        assert!(select(false, Some(Backend::Doas), &[Backend::Sudo]).is_err());
+        assert!(select(false, None, &[]).is_err());
+        // Test that escalating without the helper fails
+        assert!(Escalation::select(false, None, &all, None)
+            .unwrap_err()
+            .to_string()
+            .contains("rhino-config-helper"));
+    }
+
+    #[test]
+    fn test_escalating_runner() -> Result<(), Box<dyn Error>> {
+        // Test that helper requests are sent to the helper
+        let mut recorder = RecordingRunner::default();
+        let mut runner = EscalatingRunner::new(&mut recorder, backend(Backend::Doas));
+        runner.run(&Request::PurgeSnapd.command())?;
+        runner.run(&ExternalCommand::new("tar", ["-czf", "snap.tar.gz"]))?;
+        assert_eq!(
+            recorder.command_lines(),
+            ["doas /usr/bin/rhino-config-helper", "tar -czf snap.tar.gz"]
+        );
+        assert_eq!(
+            recorder.commands[0].input.as_deref(),
+            Some(r#"{"operation":"purge_snapd"}"#)
+        );
+
+        // Test that root runs the commands of requests as they are
+        let mut recorder = RecordingRunner::default();
+        let mut runner = EscalatingRunner::new(&mut recorder, Escalation::None);
+        runner.run(&Request::PurgeSnapd.command())?;
+        assert_eq!(
+            recorder.command_lines(),
+            ["apt autopurge snapd gnome-software-plugin-snap -y"]
+        );
+
+        // Test that the password is only asked for where it is cached
+        let mut recorder = RecordingRunner::default();
+        backend(Backend::Sudo).authenticate(&mut recorder)?;
+        backend(Backend::Pkexec).authenticate(&mut recorder)?;
+        assert_eq!(recorder.command_lines(), ["sudo -v"]);
+        let mut recorder = RecordingRunner::default().fail_on("sudo -v");
+        assert!(backend(Backend::Sudo).authenticate(&mut recorder).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,496 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::apt::{Pin, SNAPD_PIN_PRIORITY};
+use crate::commands::disable::PACSTALL_UNINSTALLER;
+use crate::config::{self, SYSTEM_CONFIG_PATH};
+use crate::pacstall::INSTALLER_CACHE;
+use crate::runner::{CommandRunner, ExternalCommand};
+use crate::script::Script;
+use crate::user;
+
+/// Name of the helper binary, installed next to `rhino-config`.
+pub const HELPER: &str = "rhino-config-helper";
+
+/// Directory the helper keeps its verified copies of scripts in.
+pub const CACHE_DIR: &str = "/var/cache/rhino-config";
+
+/// A script the helper runs as root.
+///
+/// The helper only runs the script as configured by root, in the system-wide
+/// config or by default, and downloads and verifies it itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptKind {
+    PacstallInstaller,
+    PacstallUninstaller,
+}
+
+impl ScriptKind {
+    /// Name of the cached copy of the script.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ScriptKind::PacstallInstaller => INSTALLER_CACHE,
+            ScriptKind::PacstallUninstaller => PACSTALL_UNINSTALLER,
+        }
+    }
+
+    /// The script as configured in the system-wide config at `system_file`,
+    /// which has to be owned by and only writable by the running user.
+    pub fn trusted(self, system_file: &Path) -> Result<Script> {
+        if let Ok(metadata) = fs::metadata(system_file) {
+            ensure!(
+                Some(metadata.uid()) == user::euid() && metadata.mode() & 0o022 == 0,
+                "{} can be changed by other users than root, so its scripts are not run!",
+                system_file.display()
+            );
+        }
+        let config = config::load_system(system_file)?;
+        match self {
+            ScriptKind::PacstallInstaller => config.pacstall.installer.context(format!(
+                "No Pacstall installer is configured in {}!",
+                system_file.display()
+            )),
+            ScriptKind::PacstallUninstaller => config.pacstall.uninstaller.context(format!(
+                "No Pacstall uninstaller is configured in {}!",
+                system_file.display()
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ScriptKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ScriptKind::PacstallInstaller => "the Pacstall installer",
+            ScriptKind::PacstallUninstaller => "the Pacstall uninstaller",
+        })
+    }
+}
+
+/// Where the helper finds what only root can change.
+pub struct Trusted {
+    /// The system-wide config, see [`SYSTEM_CONFIG_PATH`].
+    pub system_file: PathBuf,
+    /// See [`CACHE_DIR`].
+    pub cache_dir: PathBuf,
+}
+
+impl Default for Trusted {
+    fn default() -> Self {
+        Trusted {
+            system_file: PathBuf::from(SYSTEM_CONFIG_PATH),
+            cache_dir: PathBuf::from(CACHE_DIR),
+        }
+    }
+}
+
+/// An operation the helper runs as root.
+///
+/// The front-end sends these to the helper on its standard input instead of
+/// command lines, and the helper checks their arguments itself, so only these
+/// operations can ever be run as root through it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    /// Remove the snapd cache.
+    ClearSnapdCache,
+    /// Purge snapd along with its GNOME Software plugin.
+    PurgeSnapd,
+    /// Install snapd along with its GNOME Software plugin.
+    InstallSnapd,
+    Hold {
+        package: String,
+    },
+    Unhold {
+        package: String,
+    },
+    /// Install Flatpak along with its GNOME Software plugin.
+    InstallFlatpak,
+    /// Add a Flatpak remote unless it exists.
+    AddRemote {
+        name: String,
+        url: String,
+    },
+    /// Install Flatpak apps from a remote.
+    InstallApps {
+        remote: String,
+        apps: Vec<String>,
+    },
+    /// Write the apt preferences pin of a package, see [`Pin::new`]. Only
+    /// the pin keeping snapd out is accepted.
+    WritePin {
+        package: String,
+        priority: i32,
+    },
+    /// Remove the apt preferences pin of a package.
+    RemovePin {
+        package: String,
+    },
+    /// Run a script with `bash`, see [`ScriptKind`].
+    ///
+    /// `source` and `sha256` are what the caller expects to be run, the
+    /// request is refused if root configured another script.
+    RunScript {
+        script: ScriptKind,
+        source: String,
+        sha256: String,
+    },
+}
+
+/// Whether `package` is a valid Debian package name.
+fn is_package(package: &str) -> bool {
+    package.len() >= 2
+        && package.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && package
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+}
+
+/// Whether `name` is a valid Flatpak remote name.
+fn is_remote(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+/// Whether `app` is a valid Flatpak app id, such as `org.mozilla.firefox`.
+fn is_app(app: &str) -> bool {
+    let parts = app.split('.').collect::<Vec<_>>();
+    parts.len() >= 3
+        && parts.iter().all(|part| {
+            part.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+        })
+}
+
+/// Whether `sha256` is a SHA-256 checksum in hex.
+fn is_sha256(sha256: &str) -> bool {
+    sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether `url` is an `https` URL without spaces or control characters.
+fn is_url(url: &str) -> bool {
+    url.strip_prefix("https://")
+        .is_some_and(|rest| !rest.is_empty())
+        && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+impl Request {
+    /// Check the arguments of the request.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Request::Hold { package } | Request::Unhold { package } => {
+                ensure!(is_package(package), "{} is not a package name!", package)
+            },
+            Request::WritePin { package, priority } => ensure!(
+                package == "snapd" && *priority == SNAPD_PIN_PRIORITY,
+                "Only snapd can be pinned, to priority {}!",
+                SNAPD_PIN_PRIORITY
+            ),
+            Request::RemovePin { package } => {
+                ensure!(package == "snapd", "Only the pin of snapd can be removed!")
+            },
+            Request::AddRemote { name, url } => {
+                ensure!(is_remote(name), "{} is not a Flatpak remote name!", name);
+                ensure!(is_url(url), "{} is not an https URL!", url);
+            },
+            Request::InstallApps { remote, apps } => {
+                ensure!(
+                    is_remote(remote),
+                    "{} is not a Flatpak remote name!",
+                    remote
+                );
+                ensure!(!apps.is_empty(), "No Flatpak apps to install!");
+                if let Some(app) = apps.iter().find(|app| !is_app(app)) {
+                    bail!("{} is not a Flatpak app id!", app);
+                }
+            },
+            Request::RunScript { sha256, .. } => {
+                ensure!(is_sha256(sha256), "{} is not a SHA-256 checksum!", sha256)
+            },
+            Request::ClearSnapdCache
+            | Request::PurgeSnapd
+            | Request::InstallSnapd
+            | Request::InstallFlatpak => {},
+        }
+        Ok(())
+    }
+
+    /// The command carrying out the request, run as root.
+    ///
+    /// Runs through the helper when escalating, see [`crate::escalation`].
+    pub fn command(&self) -> ExternalCommand {
+        let command = match self {
+            Request::ClearSnapdCache => ExternalCommand::new("rm", ["-rf", "/var/cache/snapd/"]),
+            Request::PurgeSnapd => ExternalCommand::new(
+                "apt",
+                ["autopurge", "snapd", "gnome-software-plugin-snap", "-y"],
+            ),
+            Request::InstallSnapd => ExternalCommand::new(
+                "apt",
+                ["install", "snapd", "gnome-software-plugin-snap", "-y"],
+            ),
+            Request::Hold { package } => ExternalCommand::new("apt-mark", ["hold", package]),
+            Request::Unhold { package } => ExternalCommand::new("apt-mark", ["unhold", package]),
+            Request::InstallFlatpak => ExternalCommand::new(
+                "apt",
+                ["install", "flatpak", "gnome-software-plugin-flatpak", "-y"],
+            ),
+            Request::AddRemote { name, url } => {
+                ExternalCommand::new("flatpak", ["remote-add", "--if-not-exists", name, url])
+            },
+            Request::InstallApps { remote, apps } => ExternalCommand::new(
+                "flatpak",
+                ["install", "-y", "--noninteractive", remote]
+                    .into_iter()
+                    .chain(apps.iter().map(String::as_str)),
+            ),
+            Request::WritePin { package, priority } => {
+                let pin = Pin::new(package, *priority);
+                ExternalCommand::new(
+                    "install",
+                    ["-m", "644", "/dev/stdin", &pin.path.to_string_lossy()],
+                )
+                .with_input(pin.contents())
+            },
+            Request::RemovePin { package } => {
+                let path = Pin::new(package, 0).path;
+                ExternalCommand::new("rm", ["-f", &path.to_string_lossy()])
+            },
+            Request::RunScript { script, .. } => ExternalCommand::new(
+                "bash",
+                [Path::new(CACHE_DIR)
+                    .join(script.file_name())
+                    .to_string_lossy()],
+            ),
+        };
+        ExternalCommand {
+            request: Some(Box::new(self.clone())),
+            ..command
+        }
+    }
+
+    /// Validate and carry out the request, as the helper does.
+    ///
+    /// Scripts are taken from `trusted`, and only run if they are the ones the
+    /// request expects.
+    pub fn execute(&self, runner: &mut dyn CommandRunner, trusted: &Trusted) -> Result<()> {
+        self.validate()?;
+        if let Request::RunScript {
+            script,
+            source,
+            sha256,
+        } = self
+        {
+            let pinned = script.trusted(&trusted.system_file)?;
+            ensure!(
+                pinned.source == *source
+                    && pinned.sha256.as_deref().map(str::to_lowercase)
+                        == Some(sha256.to_lowercase()),
+                "{} from {} is not the one configured in {}, which is the only one run as root! \
+                 Set it there, or run rhino-config as root.",
+                script,
+                source,
+                trusted.system_file.display()
+            );
+            let cache = trusted.cache_dir.join(script.file_name());
+            pinned.fetch(runner, &cache)?;
+            return runner.run(&ExternalCommand::new("bash", [cache.to_string_lossy()]));
+        }
+        runner.run(&ExternalCommand {
+            request: None,
+            ..self.command()
+        })
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::ClearSnapdCache => write!(f, "clear the snapd cache"),
+            Request::PurgeSnapd => write!(f, "purge snapd"),
+            Request::InstallSnapd => write!(f, "install snapd"),
+            Request::Hold { package } => write!(f, "hold {}", package),
+            Request::Unhold { package } => write!(f, "unhold {}", package),
+            Request::InstallFlatpak => write!(f, "install Flatpak"),
+            Request::AddRemote { name, url } => {
+                write!(f, "add the Flatpak remote {} {}", name, url)
+            },
+            Request::InstallApps { remote, apps } => {
+                write!(f, "install {} from {}", apps.join(" "), remote)
+            },
+            Request::WritePin { package, priority } => {
+                write!(f, "pin {} to priority {}", package, priority)
+            },
+            Request::RemovePin { package } => write!(f, "remove the pin of {}", package),
+            Request::RunScript {
+                script,
+                source,
+                sha256,
+            } => write!(f, "run {} from {} (SHA-256 {})", script, source, sha256),
+        }
+    }
+}
+
+/// Read a request sent to the helper.
+pub fn parse(input: &str) -> Result<Request> {
+    let request: Request =
+        serde_json::from_str(input).context("Failed to parse the request to the helper!")?;
+    request.validate()?;
+    Ok(request)
+}
+
+/// The helper installed next to the running executable, if any.
+pub fn locate() -> Option<PathBuf> {
+    let helper = std::env::current_exe().ok()?.with_file_name(HELPER);
+    helper.is_file().then_some(helper)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::tempdir;
+
+    use super::{Request, ScriptKind, Trusted};
+    use crate::runner::RecordingRunner;
+    use crate::script;
+
+    #[test]
+    fn test_parse() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            super::parse(r#"{"operation": "hold", "package": "snapd"}"#)?,
+            Request::Hold {
+                package: "snapd".to_string()
+            }
+        );
+        assert_eq!(
+            super::parse(r#"{"operation": "purge_snapd"}"#)?,
+            Request::PurgeSnapd
+        );
+
+        // Test that only the allowed operations are accepted
+        assert!(super::parse(r#"{"operation": "run", "command": "rm -rf /"}"#).is_err());
+        // Test that scripts can not be sent along
+        assert!(super::parse(&format!(
+            r#"{{"operation": "run_script", "script": "pacstall_installer", "source": "install.sh", "sha256": "{}", "contents": "id"}}"#,
+            script::sha256(b"id")
+        ))
+        .is_err());
+        assert!(
+            super::parse(r#"{"operation": "hold", "package": "snapd", "args": "-y"}"#).is_err()
+        );
+        // Test that the arguments are validated
+        for request in [
+            r#"{"operation": "hold", "package": "-oDebug::pkgProblemResolver=1"}"#,
+            r#"{"operation": "remove_pin", "package": "../../../etc/passwd"}"#,
+            r#"{"operation": "add_remote", "name": "flathub", "url": "http://example.com"}"#,
+            r#"{"operation": "add_remote", "name": "--user", "url": "https://example.com"}"#,
+            r#"{"operation": "install_apps", "remote": "flathub", "apps": ["--system"]}"#,
+            r#"{"operation": "install_apps", "remote": "flathub", "apps": []}"#,
+            r#"{"operation": "write_pin", "package": "firefox", "priority": 1001}"#,
+            r#"{"operation": "run_script", "script": "pacstall_installer", "source": "install.sh", "sha256": "00"}"#,
+        ] {
+            assert!(super::parse(request).is_err(), "{}", request);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute() -> Result<(), Box<dyn Error>> {
+        let trusted = Trusted::default();
+        let mut runner = RecordingRunner::default();
+        Request::WritePin {
+            package: "snapd".to_string(),
+            priority: -10,
+        }
+        .execute(&mut runner, &trusted)?;
+        Request::InstallApps {
+            remote: "flathub".to_string(),
+            apps: vec!["org.mozilla.firefox".to_string()],
+        }
+        .execute(&mut runner, &trusted)?;
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "install -m 644 /dev/stdin /etc/apt/preferences.d/rhino-config-snapd.pref",
+                "flatpak install -y --noninteractive flathub org.mozilla.firefox"
+            ]
+        );
+        assert_eq!(
+            runner.commands[0].input.as_deref(),
+            Some("Package: snapd\nPin: release a=*\nPin-Priority: -10\n")
+        );
+
+        assert!(Request::Hold {
+            package: "Snapd".to_string()
+        }
+        .execute(&mut runner, &trusted)
+        .is_err());
+        assert_eq!(runner.commands.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_script() -> Result<(), Box<dyn Error>> {
+        let temp_dir = tempdir()?;
+        let trusted = Trusted {
+            system_file: temp_dir.path().join("config.toml"),
+            cache_dir: temp_dir.path().join("cache"),
+        };
+        let installer = temp_dir.path().join("install.sh");
+        fs::write(&installer, "echo installed")?;
+        let request = |sha256: &[u8]| Request::RunScript {
+            script: ScriptKind::PacstallInstaller,
+            source: installer.to_str().unwrap().to_string(),
+            sha256: script::sha256(sha256),
+        };
+        let mut runner = RecordingRunner::default();
+
+        // Test that nothing is run unless root configured a script
+        assert!(request(b"echo installed")
+            .execute(&mut runner, &trusted)
+            .is_err());
+
+        fs::write(
+            &trusted.system_file,
+            format!(
+                "version = 1\n\n[pacstall.installer]\nsource = {:?}\nsha256 = {:?}\n",
+                installer.to_str().unwrap(),
+                script::sha256(b"echo installed")
+            ),
+        )?;
+        // Test that the script root configured is verified and run
+        request(b"echo installed").execute(&mut runner, &trusted)?;
+        let cache = trusted.cache_dir.join("pacstall-install.sh");
+        assert_eq!(
+            runner.command_lines(),
+            [format!("bash {}", cache.display())]
+        );
+        assert_eq!(fs::read_to_string(&cache)?, "echo installed");
+
+        // Test that a script other than the configured one is refused
+        assert!(request(b"id").execute(&mut runner, &trusted).is_err());
+        // Test that a config others can change is not trusted
+        fs::set_permissions(&trusted.system_file, fs::Permissions::from_mode(0o666))?;
+        assert!(request(b"echo installed")
+            .execute(&mut runner, &trusted)
+            .is_err());
+        assert_eq!(runner.commands.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,583 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::options::RhinoOption;
+use crate::output::Action;
+use crate::plan::{Operation, Plan, Step};
+use crate::runner::{CommandRunner, ExternalCommand};
+
+/// Name of the log of every change made, one JSON entry per line.
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// The state an option is returned to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OptionState {
+    pub option: String,
+    pub enabled: bool,
+}
+
+/// How an operation is reverted.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Undo {
+    /// Return the options to these states, in order.
+    Revert { options: Vec<OptionState> },
+    /// The operation can not be reverted, for `reason`.
+    Irreversible { reason: String },
+}
+
+impl Undo {
+    /// How `operation` is reverted, the inverse of the options it set.
+    pub fn of(operation: &Operation) -> Self {
+        let reason = match (operation.option, operation.action) {
+            (RhinoOption::Pacstall, Action::Disable)
+                if operation
+                    .steps
+                    .iter()
+                    .any(|step| matches!(step, Step::RunScript { .. })) =>
+            {
+                Some(
+                    "The Pacstall uninstaller removed Pacstall along with the packages installed \
+                     with it, which can not be brought back. Run `rhino-config enable --pacstall` \
+                     to install Pacstall again.",
+                )
+            },
+            (RhinoOption::Snapdpurge, Action::Disable) => Some(
+                "Undoing it would purge snapd and remove ~/snap again. Run `rhino-config enable \
+                 --snapdpurge` to do that on purpose.",
+            ),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            return Undo::Irreversible {
+                reason: reason.to_string(),
+            };
+        }
+
+        Undo::Revert {
+            options: operation
+                .steps
+                .iter()
+                .rev()
+                .filter_map(|step| match step {
+                    Step::SetOption {
+                        option, enabled, ..
+                    } => Some(OptionState {
+                        option: option.to_string(),
+                        enabled: !enabled,
+                    }),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An option that was enabled or disabled.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OperationRecord {
+    pub option: String,
+    pub action: Action,
+    /// How to revert the operation, `None` if it was recorded before undo
+    /// was supported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undo: Option<Undo>,
+    /// Whether every step of the operation succeeded, `None` if it was
+    /// recorded before this was, in which case [`Entry::success`] tells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub succeeded: Option<bool>,
+}
+
+/// An external command that was run.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommandRecord {
+    pub command: String,
+    /// The helper request the command carried out, as the command itself
+    /// only names the helper or the cached copy of a script.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+    /// The exit code, `None` if the command could not be started or was
+    /// killed by a signal.
+    pub status: Option<i32>,
+}
+
+/// A single invocation of rhino-config that changed the system.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Entry {
+    /// When the invocation finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub user: Option<String>,
+    pub args: Vec<String>,
+    pub operations: Vec<OperationRecord>,
+    /// The marker files that were created or removed, by the steps that
+    /// completed.
+    pub markers: Vec<PathBuf>,
+    pub commands: Vec<CommandRecord>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Entry {
+    /// Record the execution of `plan`, which completed the steps counted in
+    /// `completed` (see [`Plan::execute_tracked`]), ran `commands` and ended
+    /// with `result`.
+    pub fn new(
+        user: Option<String>,
+        args: Vec<String>,
+        plan: &Plan,
+        completed: &[usize],
+        commands: Vec<CommandRecord>,
+        result: &Result<()>,
+    ) -> Self {
+        let markers = plan
+            .operations
+            .iter()
+            .zip(completed)
+            .flat_map(|(operation, &completed)| &operation.steps[..completed])
+            .filter_map(|step| match step {
+                Step::SetOption { option, store, .. } => Some(store.marker(*option)),
+                _ => None,
+            })
+            .collect();
+
+        Entry {
+            timestamp: now(),
+            user,
+            args,
+            operations: plan
+                .operations
+                .iter()
+                .zip(completed.iter().copied().chain(iter::repeat(0)))
+                .map(|(operation, completed)| OperationRecord {
+                    option: operation.option.to_string(),
+                    action: operation.action,
+                    undo: Some(Undo::of(operation)),
+                    succeeded: Some(completed == operation.steps.len()),
+                })
+                .collect(),
+            markers,
+            commands,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|error| format!("{:#}", error)),
+        }
+    }
+
+    /// The operations that succeeded. A failed operation is rolled back, but
+    /// the operations before it stay applied.
+    pub fn applied(&self) -> impl DoubleEndedIterator<Item = &OperationRecord> {
+        self.operations
+            .iter()
+            .filter(|operation| operation.succeeded.unwrap_or(self.success))
+    }
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Append `entry` to the log at `path`.
+pub fn record(path: &Path, entry: &Entry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}!", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open {}!", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+        .context(format!("Failed to write {}!", path.display()))
+}
+
+/// Read every entry of the log at `path`, oldest first.
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error).context(format!("Failed to read {}!", path.display())),
+    };
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).context(format!(
+                "Failed to parse line {} of {}!",
+                index + 1,
+                path.display()
+            ))
+        })
+        .collect()
+}
+
+/// Which entries of the log to show.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Only entries changing this option, or a kernel of this family.
+    pub option: Option<String>,
+    /// Only entries that failed.
+    pub failed: bool,
+    /// Only entries from this time onwards, in seconds since the Unix epoch.
+    pub since: Option<u64>,
+    /// Only this many of the most recent entries.
+    pub limit: Option<usize>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &Entry) -> bool {
+        let option = self.option.as_deref().is_none_or(|option| {
+            entry.operations.iter().any(|operation| {
+                operation.option == option || operation.option.starts_with(&format!("{}-", option))
+            })
+        });
+        option
+            && !(self.failed && entry.success)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+
+    /// The entries matching the filter, oldest first.
+    pub fn apply(&self, entries: Vec<Entry>) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = entries
+            .into_iter()
+            .filter(|entry| self.matches(entry))
+            .collect();
+        if let Some(limit) = self.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        entries
+    }
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a day since the Unix epoch, the inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parse a `YYYY-MM-DD` date into the seconds since the Unix epoch at its
+/// start, in UTC.
+pub fn parse_date(date: &str) -> Result<u64> {
+    let parts = date
+        .splitn(3, '-')
+        .map(str::parse::<u32>)
+        .collect::<Result<Vec<_>, _>>();
+    match parts.as_deref() {
+        Ok(&[year, month, day]) if (1..=12).contains(&month) && (1..=31).contains(&day) => {
+            let days = days_from_civil(i64::from(year), month, day);
+            Ok(u64::try_from(days * 86400).unwrap_or_default())
+        },
+        _ => bail!("`{}` is not a date of the form YYYY-MM-DD!", date),
+    }
+}
+
+/// Format seconds since the Unix epoch as a UTC date and time.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let seconds = i64::try_from(timestamp).unwrap_or_default();
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Passes commands on to another runner, recording them and their exit
+/// statuses for the history.
+pub struct AuditRunner<'a> {
+    runner: &'a mut dyn CommandRunner,
+    pub commands: Vec<CommandRecord>,
+}
+
+impl<'a> AuditRunner<'a> {
+    pub fn new(runner: &'a mut dyn CommandRunner) -> Self {
+        AuditRunner {
+            runner,
+            commands: Vec::new(),
+        }
+    }
+
+    fn audit(&mut self, command: &ExternalCommand, status: Option<ExitStatus>) {
+        self.commands.push(CommandRecord {
+            command: command.to_string(),
+            request: command.request.as_ref().map(ToString::to_string),
+            status: status.and_then(|status| status.code()),
+        });
+    }
+}
+
+impl CommandRunner for AuditRunner<'_> {
+    fn status(&mut self, command: &ExternalCommand) -> io::Result<ExitStatus> {
+        let status = self.runner.status(command);
+        self.audit(command, status.as_ref().ok().copied());
+        status
+    }
+
+    fn output(&mut self, command: &ExternalCommand) -> io::Result<Output> {
+        let output = self.runner.output(command);
+        self.audit(command, output.as_ref().ok().map(|output| output.status));
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::path::{Path, PathBuf};
+
+    use anyhow::anyhow;
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::{AuditRunner, CommandRecord, Entry, Filter, OperationRecord};
+    use crate::config::Store;
+    use crate::escalation::{Backend, EscalatingRunner, Escalation};
+    use crate::helper::{Request, ScriptKind};
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+    use crate::output::{Action, Format, Report};
+    use crate::plan::{Operation, Plan, Step};
+    use crate::runner::{CommandRunner, RecordingRunner};
+    use crate::script::Script;
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    fn entry(timestamp: u64, option: &str, success: bool) -> Entry {
+        Entry {
+            timestamp,
+            user: Some("rhino".to_string()),
+            args: Vec::new(),
+            operations: vec![OperationRecord {
+                option: option.to_string(),
+                action: Action::Enable,
+                undo: None,
+                succeeded: None,
+            }],
+            markers: Vec::new(),
+            commands: Vec::new(),
+            success,
+            error: None,
+        }
+    }
+
+    #[rstest]
+    fn test_record(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let path = temp_dir.path().join(super::HISTORY_FILE);
+        let option = RhinoOption::Kernel(Kernel::Liquorix);
+        let mut plan = Plan::default();
+        plan.push(Operation {
+            option,
+            action: Action::Enable,
+            steps: vec![
+                Step::request(
+                    Request::Hold {
+                        package: "linux-lqx".to_string(),
+                    },
+                    "Failed!",
+                ),
+                Step::enable(&store, option),
+            ],
+            message: String::new(),
+            journal: None,
+        });
+
+        let mut recording = RecordingRunner::default().fail_on("apt-mark");
+        let mut runner = AuditRunner::new(&mut recording);
+        let mut completed = Vec::new();
+        let result = plan.execute_tracked(
+            &mut runner,
+            &mut Report::new(Format::Text, "test"),
+            &mut completed,
+        );
+        let failed = Entry::new(
+            Some("rhino".to_string()),
+            vec!["rhino-config".to_string()],
+            &plan,
+            &completed,
+            runner.commands,
+            &result,
+        );
+        super::record(&path, &failed)?;
+        // Test that the commands are recorded along with their exit status
+        assert_eq!(
+            failed.commands,
+            [CommandRecord {
+                command: "apt-mark hold linux-lqx".to_string(),
+                request: Some("hold linux-lqx".to_string()),
+                status: Some(1),
+            }]
+        );
+        // Test that the marker is not logged, as it was never reached
+        assert!(failed.markers.is_empty());
+        assert!(!failed.success);
+
+        let succeeded = Entry::new(None, Vec::new(), &plan, &[2], Vec::new(), &Ok(()));
+        assert_eq!(succeeded.markers, [store.marker(option)]);
+        super::record(&path, &succeeded)?;
+        // Test that entries are appended
+        assert_eq!(super::load(&path)?, [failed, succeeded]);
+        assert!(super::load(&temp_dir.path().join("missing"))?.is_empty());
+
+        assert_eq!(
+            Entry::new(
+                None,
+                Vec::new(),
+                &plan,
+                &[],
+                Vec::new(),
+                &Err(anyhow!("Broken!"))
+            )
+            .error,
+            Some("Broken!".to_string())
+        );
+
+        // Test that the helper request is recorded, not only the helper
+        let mut recording = RecordingRunner::default();
+        let escalation = Escalation::Backend {
+            backend: Backend::Sudo,
+            helper: PathBuf::from("/usr/bin/rhino-config-helper"),
+        };
+        let mut audit = AuditRunner::new(&mut recording);
+        EscalatingRunner::new(&mut audit, escalation).run(&Request::PurgeSnapd.command())?;
+        assert_eq!(
+            audit.commands,
+            [CommandRecord {
+                command: "sudo /usr/bin/rhino-config-helper".to_string(),
+                request: Some("purge snapd".to_string()),
+                status: Some(0),
+            }]
+        );
+
+        // Test that scripts are recorded by their source and checksum, not
+        // their contents
+        let script = Script {
+            source: "https://example.com/install.sh".to_string(),
+            sha256: Some("0".repeat(64)),
+        };
+        let mut recording = RecordingRunner::default();
+        let mut audit = AuditRunner::new(&mut recording);
+        audit.run(&script.command(
+            ScriptKind::PacstallInstaller,
+            Path::new("/cache/install.sh"),
+        ))?;
+        assert_eq!(
+            audit.commands,
+            [CommandRecord {
+                command: "bash /cache/install.sh".to_string(),
+                request: Some(format!(
+                    "run the Pacstall installer from https://example.com/install.sh (SHA-256 {})",
+                    "0".repeat(64)
+                )),
+                status: Some(0),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter() {
+        let entries = vec![
+            entry(10, &RhinoOption::Pacstall.to_string(), true),
+            entry(
+                20,
+                &RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge)).to_string(),
+                false,
+            ),
+            entry(30, &RhinoOption::Snapdpurge.to_string(), true),
+            entry(
+                40,
+                &RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Stable)).to_string(),
+                true,
+            ),
+        ];
+        let timestamps = |filter: Filter| -> Vec<u64> {
+            filter
+                .apply(entries.clone())
+                .iter()
+                .map(|entry| entry.timestamp)
+                .collect()
+        };
+
+        assert_eq!(timestamps(Filter::default()), [10, 20, 30, 40]);
+        // Test that a kernel family matches all of its variants
+        assert_eq!(
+            timestamps(Filter {
+                option: Some("xanmod".to_string()),
+                ..Filter::default()
+            }),
+            [20, 40]
+        );
+        assert_eq!(
+            timestamps(Filter {
+                failed: true,
+                ..Filter::default()
+            }),
+            [20]
+        );
+        // Test that the limit keeps the most recent entries
+        assert_eq!(
+            timestamps(Filter {
+                since: Some(20),
+                limit: Some(2),
+                ..Filter::default()
+            }),
+            [30, 40]
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(super::parse_date("1970-01-01").unwrap(), 0);
+        assert_eq!(super::parse_date("2022-07-26").unwrap(), 1658793600);
+        assert!(super::parse_date("2022-13-01").is_err());
+        assert!(super::parse_date("yesterday").is_err());
+
+        assert_eq!(
+            super::format_timestamp(1658793600 + 3723),
+            "2022-07-26 01:02:03 UTC"
+        );
+        assert_eq!(
+            super::format_timestamp(951782400),
+            "2000-02-29 00:00:00 UTC"
+        );
+    }
+}
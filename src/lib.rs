@@ -0,0 +1,57 @@
+//! Library behind rhino-config, the utility to edit the Rolling Rhino Remix
+//! config.
+//!
+//! rhino-update and other front-ends can use it to read the config without
+//! parsing the marker files themselves, and to enable or disable options the
+//! same way `rhino-config` does.
+//!
+//! Every option rhino-config knows about is listed by [`RhinoOption::all`].
+//! Their state is read through a [`Store`], and changed by building a
+//! [`Plan`] from the operations in [`commands`], which is then executed with a
+//! [`CommandRunner`]:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use rhino_config::commands::switch_kernel;
+//! use rhino_config::output::{Format, Report};
+//! use rhino_config::{Plan, RhinoOption, Store, SystemRunner};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let store = Store::new(Path::new("/home/rhino/.rhino"));
+//! for option in RhinoOption::all() {
+//!     println!("{}: {}", option, store.load()?.is_enabled(option));
+//! }
+//!
+//! let mut plan = Plan::default();
+//! plan.push(switch_kernel::enable_liquorix(&store, false)?);
+//! let mut report = Report::new(Format::Text, "switch-kernel");
+//! plan.execute(&mut SystemRunner::default(), &mut report)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod apt;
+pub mod backup;
+pub mod commands;
+pub mod config;
+pub mod error;
+pub mod escalation;
+pub mod helper;
+pub mod history;
+pub mod options;
+pub mod output;
+pub mod pacstall;
+pub mod plan;
+pub mod profile;
+pub mod runner;
+pub mod script;
+pub mod snaps;
+pub mod system;
+pub mod user;
+
+pub use crate::config::{Config, Store};
+pub use crate::error::{Error, ErrorKind};
+pub use crate::options::{Kernel, RhinoOption, XanmodVariant};
+pub use crate::plan::{Operation, Plan};
+pub use crate::runner::{CommandRunner, SystemRunner};
@@ -1,152 +1,352 @@
 mod cli;
-mod commands;
+mod prompt;
+mod render;
+mod wizard;
 
-use std::env::var;
-use std::fs;
-use std::io::{self, Write};
-use std::path::Path;
+use std::env::{self, var, var_os};
+use std::path::{Path, PathBuf};
+use std::{fs, process};
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
+use rhino_config::commands::{
+    self, config_path, disable, enable, export, import, status, switch_kernel, undo,
+};
+use rhino_config::config::{Store, SYSTEM_CONFIG_PATH};
+use rhino_config::escalation::{EscalatingRunner, Escalation};
+use rhino_config::history::{self, AuditRunner, Entry};
+use rhino_config::output::{Format, Report};
+use rhino_config::profile::Profile;
+use rhino_config::snaps::Inventory;
+use rhino_config::user::Invoker;
+use rhino_config::{Plan, RhinoOption, SystemRunner, XanmodVariant};
 
-use crate::cli::{Cli, Commands, DisableKernel, EnableKernel, SwitchKernel};
-use crate::commands::{disable, enable, switch_kernel};
-
-/// Ask the user a question.
-///
-/// Asks the user a question, and returns `true` or `false` depending upon if
-/// they answered *yes* or *no*.
-///
-/// # Arguments
-///
-/// * `message` - A string slice message to display to the user.
-///
-/// # Examples
-///
-/// ```
-/// ask("Do you want to continue?"); 
-/// ```
-fn ask(message: &str) -> bool {
-    print!("{} [Y/n] ", message);
-    io::stdout().flush().unwrap();
-
-    let mut reply = String::new();
-
-    io::stdin()
-        .read_line(&mut reply)
-        .expect("Error while reading user input.");
-
-    let reply = reply.trim().to_uppercase();
-
-    matches!(reply.as_ref(), "Y" | "")
+use crate::cli::{
+    Cli, Commands, DisableKernel, EnableKernel, OutputFormat, SwitchKernel, XanmodVariants,
+};
+use crate::prompt::Prompt;
+
+/// The format of the report for `--output`.
+fn format(output: OutputFormat) -> Format {
+    match output {
+        OutputFormat::Text => Format::Text,
+        OutputFormat::Json => Format::Json,
+    }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+/// The XanMod variants selected with flags.
+fn xanmod_variants(variants: &XanmodVariants) -> Vec<XanmodVariant> {
+    [
+        (variants.stable, XanmodVariant::Stable),
+        (variants.edge, XanmodVariant::Edge),
+        (variants.lts, XanmodVariant::Lts),
+        (variants.realtime, XanmodVariant::Realtime),
+        (variants.realtime_edge, XanmodVariant::RealtimeEdge),
+        (variants.tasktype, XanmodVariant::Tasktype),
+    ]
+    .into_iter()
+    .filter_map(|(selected, variant)| selected.then_some(variant))
+    .collect()
+}
+
+/// The name of a subcommand, as reported in the JSON output.
+fn command_name(command: &Commands) -> &'static str {
+    match command {
+        Commands::Enable(_) => "enable",
+        Commands::Disable(_) => "disable",
+        Commands::SwitchKernel(_) => "switch-kernel",
+        Commands::Status => "status",
+        Commands::ConfigPath => "config-path",
+        Commands::History(_) => "history",
+        Commands::Undo => "undo",
+        Commands::Export(_) => "export",
+        Commands::Import(_) => "import",
+    }
+}
+
+/// Whether the command changes something in the home directory: the config
+/// lives there unless `--config-dir` is given, and purging snapd removes
+/// `~/snap`.
+fn changes_home(cli: &Cli) -> bool {
+    let removes_snap = match &cli.command {
+        Commands::Enable(flag) => flag.snapdpurge || flag.interactive,
+        Commands::Undo | Commands::Import(_) => true,
+        _ => false,
+    };
+    let read_only = matches!(
+        cli.command,
+        Commands::Status
+            | Commands::ConfigPath
+            | Commands::History(_)
+            | Commands::Export(_)
+            | Commands::SwitchKernel(SwitchKernel::List)
+    );
+    !cli.dry_run && !read_only && (cli.config_dir.is_none() || removes_snap)
+}
 
-    let home_dir = var("HOME").context("Unable to find HOME environment variable!")?;
-    let home_path = Path::new(&home_dir);
+fn run(cli: &Cli, report: &mut Report) -> Result<()> {
+    let env_path = |name| {
+        var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let invoker = Invoker::detect(&mut SystemRunner::default())?;
+    // Under sudo or pkexec the environment is root's, or a mix of root's and
+    // the user's, so everything is worked out from the user's home instead
+    let (home_path, xdg_config_home) = match &invoker {
+        Invoker::Elevated(account) => (Some(account.home.clone()), None),
+        _ => (env_path("HOME"), env_path("XDG_CONFIG_HOME")),
+    };
+    if invoker == Invoker::Root && changes_home(cli) {
+        report.warning(
+            "rhino-config is running as root without SUDO_USER or PKEXEC_UID, so root's own \
+             config and ~/snap are used. Run it through sudo or pkexec to change them for a user.",
+        );
+    }
 
-    let config_path = home_path.join(".rhino/config/");
-    fs::create_dir_all(&config_path).context("Failed to create config directory!")?;
+    let store = Store::locate(
+        cli.config_dir.as_deref(),
+        xdg_config_home.as_deref(),
+        home_path.as_deref(),
+    )?
+    .with_system_file(Path::new(SYSTEM_CONFIG_PATH));
+    let state_path = store.state_dir();
 
-    let pacstall_config_path = config_path.join("pacstall");
+    if !cli.dry_run {
+        let removed = store.migrate()?;
+        if let Some(kernel) = store.load()?.kernel().filter(|_| !removed.is_empty()) {
+            for marker in &removed {
+                report.warning(&format!(
+                    "Removed {}, as only one kernel can be enabled and {} is kept.",
+                    marker.display(),
+                    RhinoOption::Kernel(kernel)
+                ));
+            }
+        }
+        hand_back(&invoker, &store, &[], report);
+    }
 
-    let snapdpurge_config_path = config_path.join("snapdpurge");
-    let snapdpurge_snap_path = home_path.join("snap/");
+    let snapdpurge_snap_path = home_path.as_ref().map(|home| home.join("snap/"));
+
+    let mut plan = Plan::default();
 
     match &cli.command {
         Commands::Enable(flag) => {
             if flag.interactive {
-                if !snapdpurge_config_path.exists() {
-                    if ask("Do you wish to remove Snapcraft (snapd) and replace it with Flatpak?") {
-                        enable::snapdpurge(&snapdpurge_config_path, &snapdpurge_snap_path)?;
-                    } else {
-                        println!(
-                            "No changes were made to the Rhino configuration, snapd has not been \
-                             purged."
-                        );
-                    }
-                }
-
-                if !pacstall_config_path.exists() {
-                    if ask(
-                        "Do you wish to enable Pacstall, an additional AUR-like package manager \
-                         for Ubuntu on this system?",
-                    ) {
-                        enable::pacstall(&pacstall_config_path)?;
-                    } else {
-                        println!(
-                            "No changes were made to the Rhino configuration, Pacstall has not \
-                             been enabled."
-                        );
-                    }
+                if cli.output == OutputFormat::Json {
+                    bail!("Interactive mode can not be used with `--output json`!");
                 }
+                let prompt = Prompt::new(cli.yes, cli.assume_no);
+                plan = wizard::wizard(
+                    &store,
+                    &prompt,
+                    report,
+                    snapdpurge_snap_path.as_deref(),
+                    cli.dry_run,
+                )?;
             }
 
             if flag.snapdpurge {
-                ensure!(
-                    !snapdpurge_config_path.exists(),
-                    "Snapdpurge is already enabled!"
-                );
-                enable::snapdpurge(&snapdpurge_config_path, &snapdpurge_snap_path)?;
+                let inventory = Inventory::detect();
+                if inventory != Inventory::default() {
+                    report.progress(inventory.describe().trim_end());
+                }
+                plan.push(enable::snapdpurge(
+                    &store,
+                    snapdpurge_snap_path
+                        .as_deref()
+                        .context("Unable to find HOME environment variable!")?,
+                    &state_path,
+                    &inventory,
+                    flag.backup,
+                )?);
             }
 
             if flag.pacstall {
-                ensure!(
-                    !pacstall_config_path.exists(),
-                    "Pacstall is already enabled!"
-                );
-                enable::pacstall(&pacstall_config_path)?;
+                plan.push(enable::pacstall(&store, &state_path)?);
             }
-
-            Ok(())
         },
         Commands::Disable(flag) => {
             if flag.snapdpurge {
-                ensure!(
-                    snapdpurge_config_path.exists(),
-                    "Snapdpurge is already disabled!"
-                );
-
-                disable::snapdpurge(&snapdpurge_config_path)?;
+                // Questions can not be asked without mixing them into the JSON
+                let restore = if cli.output == OutputFormat::Text {
+                    let prompt = Prompt::new(cli.yes, cli.assume_no);
+                    wizard::restore_step(&store, &prompt, snapdpurge_snap_path.as_deref())?
+                } else {
+                    None
+                };
+                plan.push(disable::snapdpurge(&store, &state_path, restore)?);
             }
 
             if flag.pacstall {
-                ensure!(
-                    pacstall_config_path.exists(),
-                    "Pacstall is already disabled!"
-                );
-
-                disable::pacstall(&pacstall_config_path)?;
+                plan.push(disable::pacstall(&store)?);
             }
+        },
+
+        Commands::SwitchKernel(operation) => match operation {
+            SwitchKernel::Enable(command) => plan.push(match &command.kernel {
+                EnableKernel::Xanmod(variants) => switch_kernel::enable_xanmod(
+                    &store,
+                    &xanmod_variants(variants),
+                    command.replace,
+                )?,
+                EnableKernel::Liquorix => switch_kernel::enable_liquorix(&store, command.replace)?,
+                EnableKernel::Libre => switch_kernel::enable_libre(&store, command.replace)?,
+                EnableKernel::Mainline => switch_kernel::enable_mainline(&store, command.replace)?,
+            }),
+
+            SwitchKernel::Disable(kernel) => match kernel {
+                DisableKernel::Xanmod(variants) => plan.extend(switch_kernel::disable_xanmod(
+                    &store,
+                    &xanmod_variants(variants),
+                )?),
+                DisableKernel::Liquorix => plan.push(switch_kernel::disable_liquorix(&store)?),
+                DisableKernel::Libre => plan.push(switch_kernel::disable_libre(&store)?),
+                DisableKernel::Mainline => plan.push(switch_kernel::disable_mainline(&store)?),
+            },
 
-            Ok(())
+            SwitchKernel::List => return render::kernels(&switch_kernel::list(&store)?, report),
         },
 
-        Commands::SwitchKernel(operation) => {
-            let liquorix_config_path = &config_path.join("liquorix");
-            let libre_config_path = &config_path.join("libre");
-
-            match operation {
-                SwitchKernel::Enable(kernel) => match kernel {
-                    EnableKernel::Xanmod(variants) => {
-                        switch_kernel::enable_xanmod(&config_path, variants)
-                    },
-                    EnableKernel::Liquorix => switch_kernel::enable_liquorix(liquorix_config_path),
-                    EnableKernel::Libre => switch_kernel::enable_libre(libre_config_path),
-                },
-
-                SwitchKernel::Disable(kernel) => match kernel {
-                    DisableKernel::Xanmod(variants) => {
-                        switch_kernel::disable_xanmod(&config_path, variants)
-                    },
-                    DisableKernel::Liquorix => {
-                        switch_kernel::disable_liquorix(liquorix_config_path)
-                    },
-                    DisableKernel::Libre => switch_kernel::disable_libre(libre_config_path),
-                },
+        Commands::Status => return render::status(&status::status(&store)?, report),
+        Commands::ConfigPath => {
+            return render::config_paths(&config_path::collect(&store)?, &store, report)
+        },
+        Commands::History(command) => {
+            let filter = history::Filter {
+                option: command.option.clone(),
+                failed: command.failed,
+                since: command
+                    .since
+                    .as_deref()
+                    .map(history::parse_date)
+                    .transpose()?,
+                limit: command.limit,
+            };
+            return render::history(&commands::history::history(&store, &filter)?, report);
+        },
+        Commands::Undo => {
+            let entry = undo::last(&store)?;
+            report.progress(&format!(
+                "Undoing `{}` from {}.",
+                entry.args.join(" "),
+                history::format_timestamp(entry.timestamp)
+            ));
+            if !entry.success {
+                report.progress(
+                    "It failed partway, so only the changes that succeeded are undone, the failed \
+                     one was rolled back.",
+                );
             }
+            let restore = if undo::reinstalls_snapd(&entry) && cli.output == OutputFormat::Text {
+                let prompt = Prompt::new(cli.yes, cli.assume_no);
+                wizard::restore_step(&store, &prompt, snapdpurge_snap_path.as_deref())?
+            } else {
+                None
+            };
+            plan = undo::undo(&store, &state_path, &entry, restore)?;
+        },
+        Commands::Export(command) => {
+            let profile = export::export(&store)?;
+            let file = match &command.file {
+                Some(file) => file,
+                None => return render::profile(&profile, report),
+            };
+            profile.write(file)?;
+            hand_back(&invoker, &store, &[env::current_dir()?.join(file)], report);
+            report.progress(&format!(
+                "The profile has been exported to {}.",
+                file.display()
+            ));
+            return Ok(());
+        },
+        Commands::Import(command) => {
+            let profile = Profile::read(&command.file)?;
+            let config = store.load()?;
+            for difference in import::diff(&config, &profile) {
+                report.progress(&format!(
+                    "{}: {} -> {}",
+                    difference.option, difference.current, difference.wanted
+                ));
+            }
+            let inventory = if profile.snapdpurge && !config.snapdpurge.enabled {
+                Inventory::detect()
+            } else {
+                Inventory::default()
+            };
+            plan = import::import(
+                &store,
+                &profile,
+                snapdpurge_snap_path.as_deref(),
+                &state_path,
+                &inventory,
+                command.backup,
+            )?;
         },
     }
+
+    if cli.dry_run {
+        return render::preview(&plan, report);
+    }
+
+    fs::create_dir_all(&store.marker_dir).context("Failed to create config directory!")?;
+    let mut system_runner = SystemRunner {
+        stdout_to_stderr: cli.output == OutputFormat::Json,
+    };
+    // Asking for the password up front keeps a long change from stopping
+    // halfway through to prompt again
+    let escalation = if plan.needs_root() {
+        let escalation = Escalation::detect(store.load()?.privilege.backend)?;
+        escalation.authenticate(&mut system_runner)?;
+        escalation
+    } else {
+        Escalation::None
+    };
+    let mut audit_runner = AuditRunner::new(&mut system_runner);
+    let mut runner = EscalatingRunner::new(&mut audit_runner, escalation);
+    let mut completed = Vec::new();
+    let result = plan.execute_tracked(&mut runner, report, &mut completed);
+
+    if !plan.operations.is_empty() {
+        let user = match &invoker {
+            Invoker::Elevated(account) => Some(account.name.clone()),
+            _ => var("USER").ok(),
+        };
+        let entry = Entry::new(
+            user,
+            env::args().collect(),
+            &plan,
+            &completed,
+            audit_runner.commands,
+            &result,
+        );
+        // The change has been made either way, so failing to log it is not fatal
+        if let Err(error) = history::record(&store.history_file(), &entry) {
+            report.warning(&format!("{:#}", error));
+        }
+    }
+
+    hand_back(&invoker, &store, &plan.written(), report);
+    result
+}
+
+/// Hand the config and the other `written` paths in the home directory back
+/// to the user rhino-config was elevated for, as files created as root would
+/// otherwise lock them out of them.
+fn hand_back(invoker: &Invoker, store: &Store, written: &[PathBuf], report: &mut Report) {
+    if let Invoker::Elevated(account) = invoker {
+        for path in [&store.root, &store.marker_dir].into_iter().chain(written) {
+            if let Err(error) = account.chown(path) {
+                report.warning(&format!("{:#}", error));
+            }
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut report = Report::new(format(cli.output), command_name(&cli.command));
+    let result = run(&cli, &mut report);
+    process::exit(report.finish(result));
 }
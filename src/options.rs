@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A XanMod kernel variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XanmodVariant {
+    Stable,
+    Edge,
+    Lts,
+    Realtime,
+    RealtimeEdge,
+    Tasktype,
+}
+
+impl XanmodVariant {
+    /// Every XanMod variant, in the order they are presented to the user.
+    pub const ALL: [XanmodVariant; 6] = [
+        XanmodVariant::Stable,
+        XanmodVariant::Edge,
+        XanmodVariant::Lts,
+        XanmodVariant::Realtime,
+        XanmodVariant::RealtimeEdge,
+        XanmodVariant::Tasktype,
+    ];
+
+    /// The name of the variant as used in its marker file.
+    pub fn name(self) -> &'static str {
+        match self {
+            XanmodVariant::Stable => "stable",
+            XanmodVariant::Edge => "edge",
+            XanmodVariant::Lts => "lts",
+            XanmodVariant::Realtime => "realtime",
+            XanmodVariant::RealtimeEdge => "realtime_edge",
+            XanmodVariant::Tasktype => "tasktype",
+        }
+    }
+
+    /// The apt meta package that installs the variant.
+    pub fn package(self) -> &'static str {
+        match self {
+            XanmodVariant::Stable => "linux-xanmod",
+            XanmodVariant::Edge => "linux-xanmod-edge",
+            XanmodVariant::Lts => "linux-xanmod-lts",
+            XanmodVariant::Realtime => "linux-xanmod-rt",
+            XanmodVariant::RealtimeEdge => "linux-xanmod-rt-edge",
+            XanmodVariant::Tasktype => "linux-xanmod-tt",
+        }
+    }
+}
+
+/// An alternate kernel that rhino-update can install.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    Xanmod(XanmodVariant),
+    Liquorix,
+    Libre,
+    Mainline,
+}
+
+impl Kernel {
+    /// Every alternate kernel, with each XanMod variant listed separately.
+    pub fn all() -> Vec<Kernel> {
+        XanmodVariant::ALL
+            .iter()
+            .map(|&variant| Kernel::Xanmod(variant))
+            .chain([Kernel::Liquorix, Kernel::Libre, Kernel::Mainline])
+            .collect()
+    }
+
+    /// The name of the kernel family.
+    pub fn family(self) -> &'static str {
+        match self {
+            Kernel::Xanmod(_) => "XanMod",
+            Kernel::Liquorix => "Liquorix",
+            Kernel::Libre => "Libre",
+            Kernel::Mainline => "mainline",
+        }
+    }
+
+    /// The apt meta package that installs the kernel.
+    ///
+    /// The mainline kernel is downloaded from kernel.ubuntu.com by
+    /// rhino-update and has no meta package.
+    pub fn package(self) -> Option<&'static str> {
+        match self {
+            Kernel::Xanmod(variant) => Some(variant.package()),
+            Kernel::Liquorix => Some("linux-image-liquorix-amd64"),
+            Kernel::Libre => Some("linux-libre"),
+            Kernel::Mainline => None,
+        }
+    }
+
+    /// Whether the kernel is among the installed `packages`.
+    ///
+    /// The mainline kernel is recognised by its image packages, which are
+    /// versioned like `linux-image-unsigned-6.0.2-060002-generic`.
+    pub fn is_installed(self, packages: &HashSet<String>) -> bool {
+        match self.package() {
+            Some(package) => packages.contains(package),
+            None => packages.iter().any(|package| {
+                package
+                    .strip_prefix("linux-image-unsigned-")
+                    .is_some_and(is_mainline_release)
+            }),
+        }
+    }
+
+    /// Whether a kernel release, as in `uname -r`, belongs to this kernel.
+    ///
+    /// XanMod releases only tell realtime variants apart from the others, so
+    /// several variants may match the same release.
+    pub fn matches_release(self, release: &str) -> bool {
+        match self {
+            Kernel::Xanmod(variant) => {
+                let realtime = matches!(
+                    variant,
+                    XanmodVariant::Realtime | XanmodVariant::RealtimeEdge
+                );
+                release.contains("xanmod") && release.contains("-rt") == realtime
+            },
+            Kernel::Liquorix => release.contains("liquorix"),
+            Kernel::Libre => release.ends_with("-gnu"),
+            Kernel::Mainline => is_mainline_release(release),
+        }
+    }
+}
+
+/// Mainline builds carry the upstream version as a six digit ABI number, e.g.
+/// `6.0.2-060002-generic`.
+fn is_mainline_release(release: &str) -> bool {
+    release
+        .split('-')
+        .nth(1)
+        .is_some_and(|abi| abi.len() == 6 && abi.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// A config option that can be enabled or disabled through rhino-config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RhinoOption {
+    Pacstall,
+    Snapdpurge,
+    Kernel(Kernel),
+}
+
+impl RhinoOption {
+    /// Every option rhino-config knows about.
+    pub fn all() -> Vec<RhinoOption> {
+        [RhinoOption::Pacstall, RhinoOption::Snapdpurge]
+            .into_iter()
+            .chain(Kernel::all().into_iter().map(RhinoOption::Kernel))
+            .collect()
+    }
+
+    /// The name of the marker file inside the config directory.
+    pub fn marker(self) -> String {
+        match self {
+            RhinoOption::Pacstall => "pacstall".to_string(),
+            RhinoOption::Snapdpurge => "snapdpurge".to_string(),
+            RhinoOption::Kernel(Kernel::Xanmod(variant)) => format!("xanmod-{}", variant.name()),
+            RhinoOption::Kernel(Kernel::Liquorix) => "liquorix".to_string(),
+            RhinoOption::Kernel(Kernel::Libre) => "libre".to_string(),
+            RhinoOption::Kernel(Kernel::Mainline) => "mainline".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RhinoOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.pad(&self.marker()) }
+}
+
+impl Serialize for RhinoOption {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for RhinoOption {
+    type Err = Error;
+
+    /// Parse an option from the name of its marker file.
+    fn from_str(marker: &str) -> Result<Self, Self::Err> {
+        RhinoOption::all()
+            .into_iter()
+            .find(|option| option.marker() == marker)
+            .ok_or_else(|| anyhow!("Unknown option: `{}`", marker))
+    }
+}
+
+impl Serialize for Kernel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&RhinoOption::Kernel(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Kernel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match RhinoOption::deserialize(deserializer)? {
+            RhinoOption::Kernel(kernel) => Ok(kernel),
+            option => Err(de::Error::custom(format!("`{}` is not a kernel", option))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RhinoOption {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::ErrorKind;
+
+/// The format a [`Report`] is rendered in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable messages, printed as they are reported.
+    Text,
+    /// A single JSON document, printed once the command has finished.
+    Json,
+}
+
+/// What was done to an option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Enable,
+    Disable,
+}
+
+/// A change made to a single option.
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub option: String,
+    pub action: Action,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Success,
+    Failure,
+}
+
+#[derive(Serialize)]
+struct ErrorDocument {
+    kind: ErrorKind,
+    exit_code: i32,
+    message: String,
+    causes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    command: &'a str,
+    outcome: Outcome,
+    changes: &'a [Change],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<&'a Value>,
+    error: Option<ErrorDocument>,
+}
+
+/// Collects the result of a command and renders it in the requested format.
+///
+/// In text mode messages are printed as soon as they are reported, in JSON
+/// mode nothing is printed until [`Report::finish`] emits a single document.
+pub struct Report {
+    format: Format,
+    command: &'static str,
+    changes: Vec<Change>,
+    data: Option<Value>,
+}
+
+impl Report {
+    /// A report for `command`, rendered in `format`.
+    pub fn new(format: Format, command: &'static str) -> Self {
+        Report {
+            format,
+            command,
+            changes: Vec::new(),
+            data: None,
+        }
+    }
+
+    /// The format the report is rendered in.
+    pub fn format(&self) -> Format { self.format }
+
+    /// Record a change that has been made to an option.
+    pub fn changed(&mut self, option: impl Display, action: Action, message: impl Into<String>) {
+        let message = message.into();
+        if self.format == Format::Text {
+            println!("{}", message);
+        }
+        self.changes.push(Change {
+            option: option.to_string(),
+            action,
+            message: message.trim().to_string(),
+        });
+    }
+
+    /// Print a progress message. Only shown in text mode.
+    pub fn progress(&self, message: &str) {
+        if self.format == Format::Text {
+            println!("{}", message);
+        }
+    }
+
+    /// Print a warning, in both formats as it goes to stderr.
+    pub fn warning(&self, message: &str) {
+        eprintln!("Warning: {}", message);
+    }
+
+    /// Attach command specific data to the JSON document.
+    pub fn set_data(&mut self, data: Value) { self.data = Some(data); }
+
+    /// Render the final result and return the exit code to terminate with.
+    pub fn finish(self, result: Result<()>) -> i32 {
+        let exit_code = match &result {
+            Ok(()) => 0,
+            Err(error) => ErrorKind::of(error).exit_code(),
+        };
+
+        match self.format {
+            Format::Text => {
+                if let Err(error) = &result {
+                    eprintln!("Error: {:?}", error);
+                }
+            },
+            Format::Json => {
+                let document = Document {
+                    command: self.command,
+                    outcome: match &result {
+                        Ok(()) => Outcome::Success,
+                        Err(_) => Outcome::Failure,
+                    },
+                    changes: &self.changes,
+                    data: self.data.as_ref(),
+                    error: result.err().map(|error| ErrorDocument {
+                        kind: ErrorKind::of(&error),
+                        exit_code,
+                        message: error.to_string(),
+                        causes: error.chain().skip(1).map(ToString::to_string).collect(),
+                    }),
+                };
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&document)
+                        .expect("Failed to serialize the result document.")
+                );
+            },
+        }
+
+        exit_code
+    }
+
+    #[cfg(test)]
+    pub fn changes(&self) -> &[Change] { &self.changes }
+}
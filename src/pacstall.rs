@@ -0,0 +1,171 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::helper::ScriptKind;
+use crate::runner::{CommandRunner, ExternalCommand};
+use crate::script::Script;
+
+/// Name of the file in the state directory which records the install state.
+pub const STATE_FILE: &str = "pacstall.json";
+
+/// Name of the cached copy of the Pacstall installer.
+pub const INSTALLER_CACHE: &str = "pacstall-install.sh";
+
+/// Whether enabling Pacstall actually got it installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallState {
+    /// Pacstall is left for rhino-update to install.
+    Requested,
+    /// `pacstall` has been found on the system.
+    Installed,
+    /// The installer failed, or did not leave a working `pacstall` behind.
+    Failed,
+}
+
+impl InstallState {
+    /// Load the recorded state, `None` if nothing has been recorded yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read {}!", path.display()))?;
+        Ok(Some(
+            serde_json::from_str(&contents)
+                .context(format!("Failed to parse {}!", path.display()))?,
+        ))
+    }
+
+    /// Record the state at `path`.
+    pub fn save(self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create {}!", parent.display()))?;
+        }
+        fs::write(path, serde_json::to_string(&self)?)
+            .context(format!("Failed to write {}!", path.display()))
+    }
+}
+
+/// Whether `pacstall` is installed and can be run.
+pub fn is_installed(runner: &mut dyn CommandRunner) -> Result<bool> {
+    match runner.output(&ExternalCommand::new("pacstall", ["-V"])) {
+        Ok(output) => Ok(output.status.success()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error).context("Failed to check whether Pacstall is installed!"),
+    }
+}
+
+/// Install Pacstall with `installer` unless it is already installed, and
+/// record the outcome at `state_path`.
+///
+/// Without an installer Pacstall is left for rhino-update to install.
+pub fn install(
+    runner: &mut dyn CommandRunner,
+    installer: Option<&Script>,
+    cache: &Path,
+    state_path: &Path,
+) -> Result<InstallState> {
+    if is_installed(runner)? {
+        InstallState::Installed.save(state_path)?;
+        return Ok(InstallState::Installed);
+    }
+    let installer = match installer {
+        Some(installer) => installer,
+        None => {
+            InstallState::Requested.save(state_path)?;
+            return Ok(InstallState::Requested);
+        },
+    };
+
+    let result = installer
+        .fetch(runner, cache)
+        .and_then(|_| {
+            runner
+                .run(&installer.command(ScriptKind::PacstallInstaller, cache))
+                .context(format!(
+                    "Failed to run the script from {}!",
+                    installer.source
+                ))
+        })
+        .and_then(|_| is_installed(runner));
+    // Any error, including failing to check the outcome, counts as a failure
+    let state = match result {
+        Ok(true) => InstallState::Installed,
+        _ => InstallState::Failed,
+    };
+    state.save(state_path)?;
+
+    ensure!(
+        result?,
+        "The Pacstall installer finished, but `pacstall` can still not be run!"
+    );
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::InstallState;
+    use crate::runner::RecordingRunner;
+    use crate::script::{sha256, Script};
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_install(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let state_path = temp_dir.path().join("state/pacstall.json");
+        let cache = temp_dir.path().join("cache/install.sh");
+        let script_path = temp_dir.path().join("install.sh");
+        fs::write(&script_path, "echo installed")?;
+        let installer = Script {
+            source: script_path.to_str().unwrap().to_string(),
+            sha256: Some(sha256(b"echo installed")),
+        };
+
+        // Test that an installed Pacstall is detected without the installer
+        let mut runner = RecordingRunner::default();
+        let state = super::install(&mut runner, Some(&installer), &cache, &state_path)?;
+        assert_eq!(state, InstallState::Installed);
+        assert_eq!(runner.command_lines(), ["pacstall -V"]);
+
+        // Test that it is left to rhino-update without an installer
+        let mut runner = RecordingRunner::default().missing("pacstall");
+        assert_eq!(
+            super::install(&mut runner, None, &cache, &state_path)?,
+            InstallState::Requested
+        );
+        assert_eq!(
+            InstallState::load(&state_path)?,
+            Some(InstallState::Requested)
+        );
+
+        // Test that a failing installer is recorded
+        let mut failing = RecordingRunner::default()
+            .missing("pacstall")
+            .fail_on("bash");
+        assert!(super::install(&mut failing, Some(&installer), &cache, &state_path).is_err());
+        assert_eq!(InstallState::load(&state_path)?, Some(InstallState::Failed));
+        InstallState::Requested.save(&state_path)?;
+
+        // Test that an install which does not leave `pacstall` behind fails
+        assert!(super::install(&mut runner, Some(&installer), &cache, &state_path).is_err());
+        assert!(runner
+            .command_lines()
+            .contains(&format!("bash {}", cache.display())));
+        assert_eq!(InstallState::load(&state_path)?, Some(InstallState::Failed));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,758 @@
+use std::path::PathBuf;
+use std::{fmt, fs};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::apt::{Pin, Tracked};
+use crate::backup::Backup;
+use crate::config::Store;
+use crate::helper::{Request, ScriptKind};
+use crate::options::RhinoOption;
+use crate::output::{Action, Report};
+use crate::pacstall::{self, InstallState};
+use crate::runner::{CommandRunner, ExternalCommand};
+use crate::script::Script;
+
+/// A single change to the system.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    /// Enable or disable an option in the config, along with its marker file.
+    SetOption {
+        option: RhinoOption,
+        enabled: bool,
+        store: Store,
+    },
+
+    /// Recursively remove a directory.
+    RemoveDir { path: PathBuf },
+
+    /// Back up a directory into a tarball with a manifest, unless it does not
+    /// exist.
+    Archive { backup: Backup },
+
+    /// Extract a backup over the directory it was taken of.
+    Restore { backup: Backup },
+
+    /// Run an external command.
+    Run {
+        command: ExternalCommand,
+        /// Printed before the command is run.
+        #[serde(skip)]
+        progress: Option<&'static str>,
+        /// Attached to the error if the command fails.
+        #[serde(skip)]
+        context: &'static str,
+        /// Undoes the command if a later step fails.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rollback: Option<ExternalCommand>,
+    },
+
+    /// Write `contents` to the file at `path`.
+    WriteFile { path: PathBuf, contents: String },
+
+    /// Fetch a pinned shell script, caching it at `cache`, and run it through
+    /// `bash`.
+    RunScript {
+        script: Script,
+        #[serde(skip)]
+        kind: ScriptKind,
+        #[serde(skip)]
+        cache: PathBuf,
+    },
+
+    /// Install Pacstall with `installer` unless it is already installed,
+    /// recording whether it got installed in `state`. See
+    /// [`pacstall::install`].
+    InstallPacstall {
+        installer: Option<Script>,
+        #[serde(skip)]
+        cache: PathBuf,
+        #[serde(skip)]
+        state: PathBuf,
+    },
+
+    /// Hold a package with `apt-mark`, recording the hold in `tracking`
+    /// unless the package was already held.
+    Hold {
+        package: String,
+        #[serde(skip)]
+        tracking: PathBuf,
+    },
+
+    /// Release a hold with `apt-mark` and stop tracking it.
+    Unhold {
+        package: String,
+        #[serde(skip)]
+        tracking: PathBuf,
+    },
+
+    /// Write an apt preferences pin, recording it in `tracking`.
+    Pin {
+        pin: Pin,
+        #[serde(skip)]
+        tracking: PathBuf,
+    },
+
+    /// Remove an apt preferences pin and stop tracking it.
+    Unpin {
+        pin: Pin,
+        #[serde(skip)]
+        tracking: PathBuf,
+    },
+}
+
+impl Step {
+    /// Enable `option` in `store`.
+    pub fn enable(store: &Store, option: RhinoOption) -> Self {
+        Step::SetOption {
+            option,
+            enabled: true,
+            store: store.clone(),
+        }
+    }
+
+    /// Disable `option` in `store`.
+    pub fn disable(store: &Store, option: RhinoOption) -> Self {
+        Step::SetOption {
+            option,
+            enabled: false,
+            store: store.clone(),
+        }
+    }
+
+    /// Run `command` with `args`, failing with `context` if it fails.
+    pub fn run<I, S>(program: &str, args: I, context: &'static str) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Step::Run {
+            command: ExternalCommand::new(program, args),
+            progress: None,
+            context,
+            rollback: None,
+        }
+    }
+
+    /// Carry out `request` as root, failing with `context` if it fails.
+    pub fn request(request: Request, context: &'static str) -> Self {
+        Step::Run {
+            command: request.command(),
+            progress: None,
+            context,
+            rollback: None,
+        }
+    }
+
+    /// Carry out `request` as root to undo the step if a later step fails.
+    pub fn with_rollback(mut self, request: Request) -> Self {
+        if let Step::Run { rollback, .. } = &mut self {
+            *rollback = Some(request.command());
+        }
+        self
+    }
+
+    /// Whether the step runs anything as root.
+    pub fn needs_root(&self) -> bool {
+        match self {
+            Step::Run { command, .. } => command.request.is_some(),
+            // Without an installer Pacstall is only looked for
+            Step::InstallPacstall { installer, .. } => installer.is_some(),
+            Step::RunScript { .. }
+            | Step::Hold { .. }
+            | Step::Unhold { .. }
+            | Step::Pin { .. }
+            | Step::Unpin { .. } => true,
+            Step::SetOption { .. }
+            | Step::RemoveDir { .. }
+            | Step::Archive { .. }
+            | Step::Restore { .. }
+            | Step::WriteFile { .. } => false,
+        }
+    }
+
+    /// The paths the step writes itself rather than through a command, which
+    /// end up owned by whoever runs rhino-config.
+    pub fn written(&self) -> Vec<PathBuf> {
+        match self {
+            Step::SetOption { option, store, .. } => {
+                vec![store.file.clone(), store.marker(*option)]
+            },
+            Step::Archive { backup } => vec![backup.archive.clone(), backup.manifest()],
+            Step::Restore { backup } => vec![backup.source.clone()],
+            Step::WriteFile { path, .. } | Step::RunScript { cache: path, .. } => {
+                vec![path.clone()]
+            },
+            Step::InstallPacstall { cache, state, .. } => vec![cache.clone(), state.clone()],
+            Step::Hold { tracking, .. }
+            | Step::Unhold { tracking, .. }
+            | Step::Pin { tracking, .. }
+            | Step::Unpin { tracking, .. } => vec![tracking.clone()],
+            Step::RemoveDir { .. } | Step::Run { .. } => Vec::new(),
+        }
+    }
+
+    /// The step that undoes this one, if it can be undone.
+    pub fn rollback(&self) -> Option<Step> {
+        match self {
+            Step::SetOption {
+                option,
+                enabled,
+                store,
+            } => Some(Step::SetOption {
+                option: *option,
+                enabled: !enabled,
+                store: store.clone(),
+            }),
+            Step::Run {
+                rollback: Some(command),
+                context,
+                ..
+            } => Some(Step::Run {
+                command: command.clone(),
+                progress: None,
+                context,
+                rollback: None,
+            }),
+            Step::Hold { package, tracking } => Some(Step::Unhold {
+                package: package.clone(),
+                tracking: tracking.clone(),
+            }),
+            Step::Unhold { package, tracking } => Some(Step::Hold {
+                package: package.clone(),
+                tracking: tracking.clone(),
+            }),
+            Step::Pin { pin, tracking } => Some(Step::Unpin {
+                pin: pin.clone(),
+                tracking: tracking.clone(),
+            }),
+            Step::Unpin { pin, tracking } => Some(Step::Pin {
+                pin: pin.clone(),
+                tracking: tracking.clone(),
+            }),
+            Step::RemoveDir { .. }
+            | Step::Archive { .. }
+            | Step::Restore { .. }
+            | Step::WriteFile { .. }
+            | Step::Run { rollback: None, .. }
+            | Step::RunScript { .. }
+            | Step::InstallPacstall { .. } => None,
+        }
+    }
+
+    /// Print `message` before the step is executed.
+    pub fn with_progress(mut self, message: &'static str) -> Self {
+        if let Step::Run { progress, .. } = &mut self {
+            *progress = Some(message);
+        }
+        self
+    }
+
+    fn execute(&self, runner: &mut dyn CommandRunner, report: &Report) -> Result<()> {
+        match self {
+            Step::SetOption {
+                option,
+                enabled,
+                store,
+            } => store.set(*option, *enabled)?,
+            Step::RemoveDir { path } => {
+                // Already being gone is fine, so that a failed operation can
+                // simply be run again
+                if path.exists() {
+                    fs::remove_dir_all(path)
+                        .context(format!("Failed to remove {}!", path.display()))?;
+                }
+            },
+            Step::Archive { backup } => {
+                if backup.create(runner)? {
+                    report.progress(&format!(
+                        "Backed up {} to {}.",
+                        backup.source.display(),
+                        backup.archive.display()
+                    ));
+                }
+            },
+            Step::Restore { backup } => {
+                report.progress(&format!(
+                    "Restoring {} from {}...",
+                    backup.source.display(),
+                    backup.archive.display()
+                ));
+                backup.restore(runner)?;
+            },
+            Step::WriteFile { path, contents } => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .context(format!("Failed to create {}!", parent.display()))?;
+                }
+                fs::write(path, contents)
+                    .context(format!("Failed to write {}!", path.display()))?;
+            },
+            Step::Run {
+                command,
+                progress,
+                context,
+                ..
+            } => {
+                if let Some(progress) = progress {
+                    report.progress(progress);
+                }
+                runner.run(command).context(*context)?;
+            },
+            Step::RunScript {
+                script,
+                kind,
+                cache,
+            } => {
+                script.fetch(runner, cache)?;
+                runner
+                    .run(&script.command(*kind, cache))
+                    .context(format!("Failed to run the script from {}!", script.source))?;
+            },
+            Step::InstallPacstall {
+                installer,
+                cache,
+                state,
+            } => {
+                if let Some(installer) = installer {
+                    report.progress(&format!("Installing Pacstall from {}.", installer.source));
+                }
+                if pacstall::install(runner, installer.as_ref(), cache, state)?
+                    == InstallState::Installed
+                {
+                    report.progress("Pacstall is installed.");
+                }
+            },
+            Step::Hold { package, tracking } => {
+                let held = runner.output(&ExternalCommand::new("apt-mark", ["showhold"]))?;
+                let already_held = String::from_utf8_lossy(&held.stdout)
+                    .lines()
+                    .any(|line| line.trim() == package);
+
+                runner
+                    .run(
+                        &Request::Hold {
+                            package: package.clone(),
+                        }
+                        .command(),
+                    )
+                    .context(format!("Unable to hold {}!", package))?;
+                // Always write the tracking file, so that a hold which already
+                // existed is not mistaken for an untracked one later on
+                Tracked::update(tracking, |tracked| {
+                    if !already_held {
+                        tracked.holds.insert(package.clone());
+                    }
+                })?;
+            },
+            Step::Unhold { package, tracking } => {
+                // Leave holds alone which were not added by rhino-config. Holds
+                // added before tracking existed are not recorded anywhere, so
+                // they are released as well.
+                if let Some(tracked) = Tracked::load(tracking)? {
+                    if !tracked.holds.contains(package) {
+                        return Ok(());
+                    }
+                }
+                runner
+                    .run(
+                        &Request::Unhold {
+                            package: package.clone(),
+                        }
+                        .command(),
+                    )
+                    .context(format!("Unable to unhold {}!", package))?;
+                Tracked::update(tracking, |tracked| {
+                    tracked.holds.remove(package);
+                })?;
+            },
+            Step::Pin { pin, tracking } => {
+                runner
+                    .run(
+                        &Request::WritePin {
+                            package: pin.package.clone(),
+                            priority: pin.priority,
+                        }
+                        .command(),
+                    )
+                    .context(format!("Unable to pin {}!", pin.package))?;
+                Tracked::update(tracking, |tracked| {
+                    if !tracked.pins.contains(pin) {
+                        tracked.pins.push(pin.clone());
+                    }
+                })?;
+            },
+            Step::Unpin { pin, tracking } => {
+                runner
+                    .run(
+                        &Request::RemovePin {
+                            package: pin.package.clone(),
+                        }
+                        .command(),
+                    )
+                    .context(format!("Unable to unpin {}!", pin.package))?;
+                Tracked::update(tracking, |tracked| {
+                    tracked.pins.retain(|tracked_pin| tracked_pin != pin);
+                })?;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::SetOption {
+                option,
+                enabled,
+                store,
+            } => write!(
+                f,
+                "{} {} in {}",
+                if *enabled { "enable" } else { "disable" },
+                option,
+                store.file.display()
+            ),
+            Step::RemoveDir { path } => write!(f, "remove directory {}", path.display()),
+            Step::Archive { backup } => write!(
+                f,
+                "back up {} to {}",
+                backup.source.display(),
+                backup.archive.display()
+            ),
+            Step::Restore { backup } => write!(
+                f,
+                "restore {} from {}",
+                backup.source.display(),
+                backup.archive.display()
+            ),
+            Step::WriteFile { path, .. } => write!(f, "write {}", path.display()),
+            Step::Run { command, .. } if command.request.is_some() => {
+                write!(f, "run `{}` as root", command)
+            },
+            Step::Run { command, .. } => write!(f, "run `{}`", command),
+            Step::RunScript { script, .. } => {
+                write!(f, "run the script from {} with bash as root", script.source)
+            },
+            Step::InstallPacstall {
+                installer: Some(installer),
+                ..
+            } => write!(
+                f,
+                "install Pacstall with the script from {} unless it is installed",
+                installer.source
+            ),
+            Step::InstallPacstall {
+                installer: None, ..
+            } => {
+                write!(f, "check whether Pacstall is installed")
+            },
+            Step::Hold { package, .. } => write!(f, "hold {} with apt-mark", package),
+            Step::Unhold { package, .. } => write!(f, "unhold {} with apt-mark", package),
+            Step::Pin { pin, .. } => write!(
+                f,
+                "pin {} to priority {} in {}",
+                pin.package,
+                pin.priority,
+                pin.path.display()
+            ),
+            Step::Unpin { pin, .. } => write!(f, "remove the pin {}", pin.path.display()),
+        }
+    }
+}
+
+/// A record of a failed operation, kept so that the next attempt knows what
+/// happened.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Journal {
+    pub option: RhinoOption,
+    pub action: Action,
+    pub failed_step: String,
+    pub error: String,
+    /// Completed steps that have been undone.
+    pub rolled_back: Vec<String>,
+    /// Completed steps that could not be undone.
+    pub not_rolled_back: Vec<String>,
+}
+
+/// Enabling or disabling a single option, as a list of steps.
+///
+/// An operation is applied as a transaction: if a step fails, every completed
+/// step that can be undone is rolled back in reverse order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Operation {
+    pub option: RhinoOption,
+    pub action: Action,
+    pub steps: Vec<Step>,
+    /// Reported once every step has been executed.
+    pub message: String,
+    /// Where to record a failed attempt, see [`Journal`].
+    #[serde(skip)]
+    pub journal: Option<PathBuf>,
+}
+
+impl Operation {
+    /// Execute every step in order and report the change.
+    pub fn execute(&self, runner: &mut dyn CommandRunner, report: &mut Report) -> Result<()> {
+        self.execute_tracked(runner, report, &mut 0)
+    }
+
+    /// Like [`Operation::execute`], counting the steps that were executed
+    /// successfully in `completed`.
+    pub fn execute_tracked(
+        &self,
+        runner: &mut dyn CommandRunner,
+        report: &mut Report,
+        completed: &mut usize,
+    ) -> Result<()> {
+        if let Some(journal) = self.read_journal() {
+            report.progress(&format!(
+                "A previous attempt failed at `{}`, retrying.",
+                journal.failed_step
+            ));
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            if let Err(error) = step.execute(runner, report) {
+                self.roll_back(&self.steps[..index], step, &error, runner, report);
+                return Err(error);
+            }
+            *completed = index + 1;
+        }
+
+        if let Some(path) = &self.journal {
+            if path.exists() {
+                fs::remove_file(path).context(format!("Failed to remove {}!", path.display()))?;
+            }
+        }
+        report.changed(self.option, self.action, &self.message);
+        Ok(())
+    }
+
+    fn read_journal(&self) -> Option<Journal> {
+        let contents = fs::read_to_string(self.journal.as_ref()?).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Undo the `completed` steps after `failed` failed with `error`.
+    ///
+    /// This is best effort, steps that can not be undone are recorded in the
+    /// journal instead.
+    fn roll_back(
+        &self,
+        completed: &[Step],
+        failed: &Step,
+        error: &anyhow::Error,
+        runner: &mut dyn CommandRunner,
+        report: &Report,
+    ) {
+        let mut journal = Journal {
+            option: self.option,
+            action: self.action,
+            failed_step: failed.to_string(),
+            error: format!("{:#}", error),
+            rolled_back: Vec::new(),
+            not_rolled_back: Vec::new(),
+        };
+
+        for step in completed.iter().rev() {
+            match step.rollback() {
+                Some(rollback) => {
+                    report.progress(&format!("Rolling back: {}", rollback));
+                    match rollback.execute(runner, report) {
+                        Ok(()) => journal.rolled_back.push(step.to_string()),
+                        Err(_) => journal.not_rolled_back.push(step.to_string()),
+                    }
+                },
+                None => journal.not_rolled_back.push(step.to_string()),
+            }
+        }
+
+        if let Some(path) = &self.journal {
+            let written = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| {
+                    fs::write(
+                        path,
+                        serde_json::to_string_pretty(&journal).unwrap_or_default(),
+                    )
+                });
+            if written.is_err() {
+                report.progress(&format!(
+                    "Failed to record the failure in {}",
+                    path.display()
+                ));
+            }
+        }
+    }
+}
+
+/// Every change a command is going to make.
+///
+/// Commands build the full plan before touching the system, so that it can
+/// either be printed with `--dry-run` or executed.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub operations: Vec<Operation>,
+}
+
+impl Plan {
+    /// Add an operation to the end of the plan.
+    pub fn push(&mut self, operation: Operation) { self.operations.push(operation); }
+
+    /// Whether any of the operations runs something as root.
+    pub fn needs_root(&self) -> bool {
+        self.operations
+            .iter()
+            .flat_map(|operation| &operation.steps)
+            .any(Step::needs_root)
+    }
+
+    /// The paths any of the operations writes, see [`Step::written`].
+    pub fn written(&self) -> Vec<PathBuf> {
+        self.operations
+            .iter()
+            .flat_map(|operation| &operation.steps)
+            .flat_map(Step::written)
+            .collect()
+    }
+
+    /// Execute every operation in order.
+    pub fn execute(&self, runner: &mut dyn CommandRunner, report: &mut Report) -> Result<()> {
+        self.execute_tracked(runner, report, &mut Vec::new())
+    }
+
+    /// Like [`Plan::execute`], counting the steps of every operation that was
+    /// started which were executed successfully in `completed`.
+    pub fn execute_tracked(
+        &self,
+        runner: &mut dyn CommandRunner,
+        report: &mut Report,
+        completed: &mut Vec<usize>,
+    ) -> Result<()> {
+        for operation in &self.operations {
+            let mut count = 0;
+            let result = operation.execute_tracked(runner, report, &mut count);
+            completed.push(count);
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl Extend<Operation> for Plan {
+    fn extend<T: IntoIterator<Item = Operation>>(&mut self, operations: T) {
+        self.operations.extend(operations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::path::{Path, PathBuf};
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::{Operation, Plan, Step};
+    use crate::apt::Pin;
+    use crate::backup::Backup;
+    use crate::config::Store;
+    use crate::helper::Request;
+    use crate::options::{Kernel, RhinoOption};
+    use crate::output::{Action, Format, Report};
+    use crate::runner::RecordingRunner;
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[fixture]
+    fn report() -> Report { Report::new(Format::Text, "test") }
+
+    #[rstest]
+    fn test_execute(temp_dir: TempDir, mut report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(temp_dir.path());
+        let libre = RhinoOption::Kernel(Kernel::Libre);
+        store.set(libre, true)?;
+
+        let mut plan = Plan::default();
+        plan.push(Operation {
+            option: RhinoOption::Pacstall,
+            action: Action::Enable,
+            steps: vec![Step::enable(&store, RhinoOption::Pacstall)],
+            message: "Pacstall has been enabled.".to_string(),
+            journal: None,
+        });
+        plan.push(Operation {
+            option: libre,
+            action: Action::Disable,
+            steps: vec![Step::disable(&store, libre)],
+            message: "The Libre kernel has been disabled.".to_string(),
+            journal: None,
+        });
+
+        // Test that building the plan does not touch the system
+        assert!(!store.load()?.is_enabled(RhinoOption::Pacstall));
+        assert!(store.load()?.is_enabled(libre));
+
+        // Test that executing the plan applies every step and reports it
+        plan.execute(&mut RecordingRunner::default(), &mut report)?;
+        assert!(store.load()?.is_enabled(RhinoOption::Pacstall));
+        assert!(!store.load()?.is_enabled(libre));
+        assert!(!store.marker(libre).exists());
+        assert_eq!(report.changes().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() {
+        let step = Step::run(
+            "tar",
+            ["-czf", "snap.tar.gz", "snap"],
+            "Unable to archive snap!",
+        );
+        assert_eq!(step.to_string(), "run `tar -czf snap.tar.gz snap`");
+        assert!(!step.needs_root());
+
+        // Test that helper requests are told apart, as they run as root
+        let step = Step::request(Request::PurgeSnapd, "Unable to purge snapd!");
+        assert_eq!(
+            step.to_string(),
+            "run `apt autopurge snapd gnome-software-plugin-snap -y` as root"
+        );
+        assert!(step.needs_root());
+
+        let step = Step::Pin {
+            pin: Pin::new("snapd", -10),
+            tracking: "apt.json".into(),
+        };
+        assert_eq!(
+            step.to_string(),
+            "pin snapd to priority -10 in /etc/apt/preferences.d/rhino-config-snapd.pref"
+        );
+    }
+
+    #[test]
+    fn test_written() {
+        let backup = Backup::new(Path::new("/home/rhino/snap"), Path::new("/backups"));
+
+        // Test that a restored directory is reported as written
+        let mut plan = Plan::default();
+        plan.push(Operation {
+            option: RhinoOption::Snapdpurge,
+            action: Action::Disable,
+            steps: vec![
+                Step::request(Request::PurgeSnapd, "Unable to purge snapd!"),
+                Step::Restore { backup },
+            ],
+            message: String::new(),
+            journal: None,
+        });
+        assert_eq!(plan.written(), [PathBuf::from("/home/rhino/snap")]);
+    }
+}
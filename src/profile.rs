@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::options::Kernel;
+
+/// Version of the profile format, bumped on incompatible changes.
+pub const PROFILE_VERSION: u32 = 1;
+
+/// The state of every option, as exported from one machine to be imported on
+/// others.
+///
+/// Unlike the config it only holds the options themselves, not where scripts
+/// are fetched from, and every option has to be given.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub version: u32,
+    pub pacstall: bool,
+    pub snapdpurge: bool,
+    /// The selected alternate kernel, `stock` for the Ubuntu kernel.
+    #[serde(with = "crate::config::selected")]
+    pub kernel: Option<Kernel>,
+}
+
+impl Profile {
+    /// The profile of the options set in `config`.
+    pub fn of(config: &Config) -> Self {
+        Profile {
+            version: PROFILE_VERSION,
+            pacstall: config.pacstall.enabled,
+            snapdpurge: config.snapdpurge.enabled,
+            kernel: config.kernel(),
+        }
+    }
+
+    /// Parse and validate a profile.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let profile: Profile = toml::from_str(contents)?;
+        ensure!(
+            profile.version <= PROFILE_VERSION,
+            "The profile is version {}, which is newer than this version of rhino-config \
+             understands!",
+            profile.version
+        );
+        Ok(profile)
+    }
+
+    /// Read and validate the profile at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read {}!", path.display()))?;
+        Profile::parse(&contents).context(format!("{} is not a valid profile!", path.display()))
+    }
+
+    /// The profile as a TOML document.
+    pub fn to_toml(&self) -> Result<String> { Ok(toml::to_string(self)?) }
+
+    /// Write the profile to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_toml()?).context(format!("Failed to write {}!", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::Profile;
+    use crate::config::Config;
+    use crate::options::{Kernel, RhinoOption, XanmodVariant};
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config::default();
+        config.set(RhinoOption::Pacstall, true);
+        config.set(
+            RhinoOption::Kernel(Kernel::Xanmod(XanmodVariant::Edge)),
+            true,
+        );
+
+        let profile = Profile::of(&config);
+        let exported = profile.to_toml().unwrap();
+        assert_eq!(
+            exported,
+            indoc!(
+                r#"
+                version = 1
+                pacstall = true
+                snapdpurge = false
+                kernel = "xanmod-edge"
+                "#
+            )
+        );
+        // Test that an exported profile can be imported again
+        assert_eq!(Profile::parse(&exported).unwrap(), profile);
+
+        // Test that the stock kernel is written explicitly
+        assert!(Profile::of(&Config::default())
+            .to_toml()
+            .unwrap()
+            .contains(r#"kernel = "stock""#));
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = indoc!(
+            r#"
+            version = 1
+            pacstall = false
+            snapdpurge = true
+            kernel = "stock"
+            "#
+        );
+        assert_eq!(Profile::parse(valid).unwrap().kernel, None);
+
+        // Test that unknown kernels, unknown keys, missing options and newer
+        // versions are refused
+        for invalid in [
+            valid.replace("stock", "xanmod-nightly"),
+            valid.replace("pacstall = false", "pacstall = false\nzfs = true"),
+            valid.replace("snapdpurge = true\n", ""),
+            valid.replace("version = 1", "version = 2"),
+        ] {
+            assert!(Profile::parse(&invalid).is_err(), "{}", invalid);
+        }
+    }
+}
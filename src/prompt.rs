@@ -0,0 +1,196 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::{bail, Context, Result};
+
+/// Asks the user yes or no questions.
+///
+/// Questions are answered from stdin, unless an answer has been given up
+/// front with `--yes` or `--assume-no`. Without one, prompting is refused if
+/// stdin is not a terminal, so that piping into rhino-config can not silently
+/// answer questions.
+pub struct Prompt {
+    assume: Option<bool>,
+}
+
+/// One of the answers to a multiple choice question.
+pub struct Choice {
+    pub name: String,
+    pub description: String,
+}
+
+impl Prompt {
+    /// # Arguments
+    ///
+    /// * `yes` - Answer *yes* to every question.
+    /// * `assume_no` - Answer *no* to every question.
+    pub fn new(yes: bool, assume_no: bool) -> Self {
+        Prompt {
+            assume: match (yes, assume_no) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    /// Ask the user a question, defaulting to *yes*.
+    ///
+    /// Returns `true` or `false` depending upon if they answered *yes* or
+    /// *no*.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// prompt.ask("Do you want to continue?")?; 
+    /// ```
+    pub fn ask(&self, message: &str) -> Result<bool> { self.ask_with_default(message, true) }
+
+    /// Ask the user a question, defaulting to *no*.
+    ///
+    /// Used for destructive changes, which should only be made when the user
+    /// explicitly answers *yes*.
+    pub fn ask_default_no(&self, message: &str) -> Result<bool> {
+        self.ask_with_default(message, false)
+    }
+
+    fn ask_with_default(&self, message: &str, default_yes: bool) -> Result<bool> {
+        let choices = if default_yes { "[Y/n]" } else { "[y/N]" };
+
+        if let Some(answer) = self.assume {
+            println!("{} {} {}", message, choices, if answer { "y" } else { "n" });
+            return Ok(answer);
+        }
+
+        Ok(parse_reply(
+            &read_reply(&format!("{} {}", message, choices))?,
+            default_yes,
+        ))
+    }
+
+    /// Ask the user to pick one of `choices`, defaulting to `default`.
+    ///
+    /// Returns the index of the picked choice, which can be given by its
+    /// number or its name. An answer given up front picks `proposed` for
+    /// `--yes` and the default for `--assume-no`.
+    pub fn choose(
+        &self,
+        message: &str,
+        choices: &[Choice],
+        default: usize,
+        proposed: usize,
+    ) -> Result<usize> {
+        println!("{}", message);
+        let width = choices
+            .iter()
+            .map(|choice| choice.name.len())
+            .max()
+            .unwrap_or(0);
+        for (number, choice) in (1..).zip(choices) {
+            println!(
+                "  {}) {:<width$}  {}",
+                number,
+                choice.name,
+                choice.description,
+                width = width
+            );
+        }
+        let question = format!("Choose [1-{}] ({})", choices.len(), choices[default].name);
+
+        if let Some(yes) = self.assume {
+            let index = if yes { proposed } else { default };
+            println!("{} {}", question, index + 1);
+            return Ok(index);
+        }
+
+        loop {
+            match parse_choice(&read_reply(&question)?, choices, default) {
+                Some(index) => return Ok(index),
+                None => println!("Please answer with a number from 1 to {}.", choices.len()),
+            }
+        }
+    }
+}
+
+/// Print `question` and read the reply from stdin.
+fn read_reply(question: &str) -> Result<String> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        bail!(
+            "Refusing to ask questions as stdin is not a terminal! Pass `--yes` or `--assume-no` \
+             to answer them."
+        );
+    }
+
+    print!("{} ", question);
+    io::stdout().flush()?;
+
+    let mut reply = String::new();
+    let read = stdin
+        .lock()
+        .read_line(&mut reply)
+        .context("Error while reading user input.")?;
+    if read == 0 {
+        bail!("No answer was given!");
+    }
+    Ok(reply)
+}
+
+fn parse_choice(reply: &str, choices: &[Choice], default: usize) -> Option<usize> {
+    let reply = reply.trim();
+    if reply.is_empty() {
+        return Some(default);
+    }
+    match reply.parse::<usize>() {
+        Ok(number) => (1..=choices.len()).contains(&number).then(|| number - 1),
+        Err(_) => choices
+            .iter()
+            .position(|choice| choice.name.eq_ignore_ascii_case(reply)),
+    }
+}
+
+fn parse_reply(reply: &str, default_yes: bool) -> bool {
+    match reply.trim().to_uppercase().as_ref() {
+        "" => default_yes,
+        "Y" | "YES" => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_choice, parse_reply, Choice, Prompt};
+
+    #[test]
+    fn test_parse_reply() {
+        assert!(parse_reply("\n", true));
+        assert!(parse_reply(" y\n", false));
+        assert!(parse_reply("Yes", false));
+        // Test that an empty reply falls back to the default
+        assert!(!parse_reply("\n", false));
+        assert!(!parse_reply("n", true));
+        assert!(!parse_reply("whatever", true));
+    }
+
+    #[test]
+    fn test_assume() {
+        // Test that assumed answers are given without reading stdin
+        assert!(Prompt::new(true, false).ask_default_no("Purge?").unwrap());
+        assert!(!Prompt::new(false, true).ask("Enable?").unwrap());
+    }
+
+    #[test]
+    fn test_parse_choice() {
+        let choices = ["stock", "liquorix", "libre"].map(|name| Choice {
+            name: name.to_string(),
+            description: String::new(),
+        });
+
+        assert_eq!(parse_choice("\n", &choices, 1), Some(1));
+        assert_eq!(parse_choice("3\n", &choices, 0), Some(2));
+        // Test that choices can be picked by name
+        assert_eq!(parse_choice(" Libre\n", &choices, 0), Some(2));
+        assert_eq!(parse_choice("0", &choices, 0), None);
+        assert_eq!(parse_choice("4", &choices, 0), None);
+        assert_eq!(parse_choice("mainline", &choices, 0), None);
+    }
+}
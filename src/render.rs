@@ -0,0 +1,210 @@
+use anyhow::Result;
+use rhino_config::commands::config_path::ConfigPaths;
+use rhino_config::commands::status::{OptionStatus, SystemState};
+use rhino_config::commands::switch_kernel::KernelList;
+use rhino_config::history::{self, Entry};
+use rhino_config::output::{Action, Format, Report};
+use rhino_config::profile::Profile;
+use rhino_config::{Plan, Store};
+use serde::Serialize;
+use serde_json::json;
+
+/// Hand `data` to the JSON report, returning whether it was.
+fn set_data<T: Serialize>(report: &mut Report, data: &T) -> Result<bool> {
+    if report.format() != Format::Json {
+        return Ok(false);
+    }
+    report.set_data(serde_json::to_value(data)?);
+    Ok(true)
+}
+
+/// The STATE column of an option.
+fn state(enabled: bool) -> &'static str {
+    if enabled {
+        "enabled"
+    } else {
+        "disabled"
+    }
+}
+
+/// Print the state of every option.
+pub fn status(statuses: &[OptionStatus], report: &mut Report) -> Result<()> {
+    if set_data(report, &statuses)? {
+        return Ok(());
+    }
+
+    println!("{:<24} {:<10} SYSTEM", "OPTION", "STATE");
+    for status in statuses {
+        let system = match &status.system {
+            SystemState::InSync => "ok",
+            SystemState::OutOfSync(message) => message,
+            SystemState::Unknown => "unknown",
+        };
+        println!(
+            "{:<24} {:<10} {}",
+            status.option,
+            state(status.enabled),
+            system
+        );
+    }
+    Ok(())
+}
+
+/// Print every alternate kernel and its state.
+pub fn kernels(list: &KernelList, report: &mut Report) -> Result<()> {
+    if set_data(report, list)? {
+        return Ok(());
+    }
+
+    println!(
+        "{:<24} {:<10} {:<10} {:<10} RUNNING",
+        "KERNEL", "FAMILY", "STATE", "INSTALLED"
+    );
+    for status in &list.kernels {
+        println!(
+            "{:<24} {:<10} {:<10} {:<10} {}",
+            status.option,
+            status.family,
+            state(status.enabled),
+            match status.installed {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "unknown",
+            },
+            if status.running { "yes" } else { "no" }
+        );
+    }
+    println!();
+    println!(
+        "Running kernel: {}",
+        list.running_release.as_deref().unwrap_or("unknown")
+    );
+    Ok(())
+}
+
+/// Print the config files of `store` and where every option comes from.
+pub fn config_paths(paths: &ConfigPaths, store: &Store, report: &mut Report) -> Result<()> {
+    if set_data(report, paths)? {
+        return Ok(());
+    }
+
+    for layer in &paths.layers {
+        println!(
+            "{:<8} {}{}",
+            layer.layer,
+            layer.path.display(),
+            if layer.exists { "" } else { " (missing)" }
+        );
+    }
+    if !store.file.exists() && store.marker_dir.exists() {
+        println!(
+            "The user config is read from the legacy markers in {} until it is written.",
+            store.marker_dir.display()
+        );
+    }
+    println!();
+
+    println!("{:<24} {:<10} LAYER", "OPTION", "STATE");
+    for option in &paths.options {
+        println!(
+            "{:<24} {:<10} {}",
+            option.option,
+            state(option.enabled),
+            option.layer
+        );
+    }
+    Ok(())
+}
+
+/// Print the logged changes.
+pub fn history(entries: &[Entry], report: &mut Report) -> Result<()> {
+    if set_data(report, &entries)? {
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No changes have been recorded.");
+    }
+    for entry in entries {
+        println!(
+            "{}  {}  {}  {}",
+            history::format_timestamp(entry.timestamp),
+            entry.user.as_deref().unwrap_or("unknown"),
+            if entry.success { "succeeded" } else { "failed" },
+            entry.args.join(" ")
+        );
+        for operation in &entry.operations {
+            let action = match operation.action {
+                Action::Enable => "enable",
+                Action::Disable => "disable",
+            };
+            if operation.succeeded == Some(false) {
+                println!(
+                    "    {} {}, failed and rolled back",
+                    action, operation.option
+                );
+            } else {
+                println!("    {} {}", action, operation.option);
+            }
+        }
+        for marker in &entry.markers {
+            println!("    touched {}", marker.display());
+        }
+        for command in &entry.commands {
+            let request = match &command.request {
+                Some(request) => format!(" to {}", request),
+                None => String::new(),
+            };
+            match command.status {
+                Some(status) => println!(
+                    "    ran `{}`{}, exited with {}",
+                    command.command, request, status
+                ),
+                None => println!(
+                    "    ran `{}`{}, did not exit normally",
+                    command.command, request
+                ),
+            }
+        }
+        if let Some(error) = &entry.error {
+            println!("    error: {}", error);
+        }
+    }
+    Ok(())
+}
+
+/// Print the exported profile.
+pub fn profile(profile: &Profile, report: &mut Report) -> Result<()> {
+    if set_data(report, profile)? {
+        return Ok(());
+    }
+
+    print!("{}", profile.to_toml()?);
+    Ok(())
+}
+
+/// Print the plan without executing it.
+pub fn preview(plan: &Plan, report: &mut Report) -> Result<()> {
+    if report.format() == Format::Json {
+        report.set_data(json!({
+            "dry_run": true,
+            "operations": serde_json::to_value(&plan.operations)?,
+        }));
+        return Ok(());
+    }
+
+    if plan.operations.is_empty() {
+        println!("Nothing to do.");
+    }
+    for operation in &plan.operations {
+        let action = match operation.action {
+            Action::Enable => "Enable",
+            Action::Disable => "Disable",
+        };
+        println!("{} {}:", action, operation.option);
+        for step in &operation.steps {
+            println!("    {}", step);
+        }
+    }
+    Ok(())
+}
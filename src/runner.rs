@@ -0,0 +1,224 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Output, Stdio};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::helper::Request;
+
+/// An external command, such as a privileged `apt` invocation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ExternalCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Written to the standard input of the command.
+    #[serde(skip)]
+    pub input: Option<String>,
+    /// The helper request the command carries out as root, see
+    /// [`crate::helper`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<Box<Request>>,
+}
+
+impl ExternalCommand {
+    /// Create a command from its program and arguments.
+    pub fn new<I, S>(program: &str, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ExternalCommand {
+            program: program.to_string(),
+            args: args.into_iter().map(Into::into).collect(),
+            input: None,
+            request: None,
+        }
+    }
+
+    /// Write `input` to the standard input of the command.
+    pub fn with_input(mut self, input: String) -> Self {
+        self.input = Some(input);
+        self
+    }
+}
+
+impl fmt::Display for ExternalCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs external commands.
+///
+/// Every external and privileged invocation goes through a runner, so that
+/// tests can swap in a `RecordingRunner` instead of touching the system.
+pub trait CommandRunner {
+    /// Run a command to completion, letting it print to the terminal.
+    fn status(&mut self, command: &ExternalCommand) -> io::Result<ExitStatus>;
+
+    /// Run a command to completion, capturing its output.
+    fn output(&mut self, command: &ExternalCommand) -> io::Result<Output>;
+
+    /// Run a command to completion, failing if it exits unsuccessfully.
+    fn run(&mut self, command: &ExternalCommand) -> Result<()> {
+        let status = self.status(command)?;
+        if !status.success() {
+            return Err(Error::CommandFailed {
+                command: command.to_string(),
+                status,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Runs commands on the actual system.
+#[derive(Default)]
+pub struct SystemRunner {
+    /// Send the output of commands to stderr, keeping stdout free for
+    /// machine-readable output.
+    pub stdout_to_stderr: bool,
+}
+
+impl CommandRunner for SystemRunner {
+    fn status(&mut self, command: &ExternalCommand) -> io::Result<ExitStatus> {
+        let mut process = Command::new(&command.program);
+        process.args(&command.args);
+        if self.stdout_to_stderr {
+            process.stdout(Stdio::from(io::stderr()));
+        }
+
+        match &command.input {
+            Some(input) => {
+                let mut child = process.stdin(Stdio::piped()).spawn()?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(input.as_bytes())?;
+                }
+                child.wait()
+            },
+            None => process.status(),
+        }
+    }
+
+    fn output(&mut self, command: &ExternalCommand) -> io::Result<Output> {
+        Command::new(&command.program).args(&command.args).output()
+    }
+}
+
+/// Records commands instead of running them.
+///
+/// Every command succeeds with empty output, unless it has been set up to
+/// fail with [`RecordingRunner::fail_on`] or [`RecordingRunner::missing`], or
+/// to print something with [`RecordingRunner::with_stdout`].
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordingRunner {
+    pub commands: Vec<ExternalCommand>,
+    failing: Vec<String>,
+    missing: Vec<String>,
+    stdout: Vec<(String, Vec<u8>)>,
+}
+
+#[cfg(test)]
+impl RecordingRunner {
+    /// Fail every command whose command line starts with `prefix`.
+    pub fn fail_on(mut self, prefix: &str) -> Self {
+        self.failing.push(prefix.to_string());
+        self
+    }
+
+    /// Fail to start `program`, as if it was not installed.
+    pub fn missing(mut self, program: &str) -> Self {
+        self.missing.push(program.to_string());
+        self
+    }
+
+    /// Print `stdout` for every command whose command line starts with
+    /// `prefix`.
+    pub fn with_stdout(mut self, prefix: &str, stdout: &str) -> Self {
+        self.stdout
+            .push((prefix.to_string(), stdout.as_bytes().to_vec()));
+        self
+    }
+
+    /// The recorded command lines.
+    pub fn command_lines(&self) -> Vec<String> {
+        self.commands.iter().map(ToString::to_string).collect()
+    }
+
+    fn spawn(&mut self, command: &ExternalCommand) -> io::Result<()> {
+        self.commands.push(command.clone());
+        if self.missing.contains(&command.program) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not installed", command.program),
+            ));
+        }
+        Ok(())
+    }
+
+    fn exit_status(&self, command_line: &str) -> ExitStatus {
+        use std::os::unix::process::ExitStatusExt;
+
+        let failed = self
+            .failing
+            .iter()
+            .any(|prefix| command_line.starts_with(prefix.as_str()));
+        ExitStatus::from_raw(if failed { 1 << 8 } else { 0 })
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for RecordingRunner {
+    fn status(&mut self, command: &ExternalCommand) -> io::Result<ExitStatus> {
+        self.spawn(command)?;
+        Ok(self.exit_status(&command.to_string()))
+    }
+
+    fn output(&mut self, command: &ExternalCommand) -> io::Result<Output> {
+        self.spawn(command)?;
+        let command_line = command.to_string();
+        let stdout = self
+            .stdout
+            .iter()
+            .find(|(prefix, _)| command_line.starts_with(prefix.as_str()))
+            .map(|(_, stdout)| stdout.clone())
+            .unwrap_or_default();
+        Ok(Output {
+            status: self.exit_status(&command_line),
+            stdout,
+            stderr: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandRunner, ExternalCommand, RecordingRunner};
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_recording_runner() {
+        let mut runner = RecordingRunner::default().fail_on("apt install");
+
+        let hold = ExternalCommand::new("apt-mark", ["hold", "snapd"]);
+        assert!(runner.run(&hold).is_ok());
+
+        // Test that failing commands are reported as such
+        let install = ExternalCommand::new("apt", ["install", "flatpak"]);
+        let error = runner.run(&install).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::CommandFailed);
+
+        assert_eq!(
+            runner.command_lines(),
+            ["apt-mark hold snapd", "apt install flatpak"]
+        );
+    }
+}
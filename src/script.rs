@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::helper::{Request, ScriptKind};
+use crate::runner::{CommandRunner, ExternalCommand};
+
+/// A shell script pinned to a SHA-256 checksum, from a URL or a local file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Script {
+    /// An `http(s)://` URL, or the path of a local copy.
+    pub source: String,
+    /// The expected SHA-256 checksum of the script, in hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// The SHA-256 checksum of `contents`, in hex.
+pub fn sha256(contents: &[u8]) -> String { format!("{:x}", Sha256::digest(contents)) }
+
+impl Script {
+    /// A script from `source` that has not been pinned yet.
+    pub fn new(source: &str) -> Self {
+        Script {
+            source: source.to_string(),
+            sha256: None,
+        }
+    }
+
+    fn is_remote(&self) -> bool {
+        self.source.starts_with("https://") || self.source.starts_with("http://")
+    }
+
+    /// Fetch the script and verify it against its checksum.
+    ///
+    /// A verified copy is kept at `cache`. As long as it still matches the
+    /// checksum it is used instead of downloading the script again, so that
+    /// the script can be run offline.
+    pub fn fetch(&self, runner: &mut dyn CommandRunner, cache: &Path) -> Result<String> {
+        let expected = match &self.sha256 {
+            Some(sha256) => sha256.to_lowercase(),
+            None => bail!(
+                "The script from {} is not pinned to a SHA-256 checksum!",
+                self.source
+            ),
+        };
+
+        if let Ok(cached) = fs::read(cache) {
+            if sha256(&cached) == expected {
+                return String::from_utf8(cached)
+                    .context(format!("Failed to read {}!", cache.display()));
+            }
+        }
+
+        let contents = if self.is_remote() {
+            download(runner, &self.source)?
+        } else {
+            let path = self.source.strip_prefix("file://").unwrap_or(&self.source);
+            fs::read(path).context(format!("Failed to read the script at {}!", path))?
+        };
+        let actual = sha256(&contents);
+        ensure!(
+            actual == expected,
+            "The script from {} does not match its pinned SHA-256 checksum! Expected {}, got {}.",
+            self.source,
+            expected,
+            actual
+        );
+
+        if let Some(parent) = cache.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create {}!", parent.display()))?;
+        }
+        fs::write(cache, &contents).context(format!("Failed to write {}!", cache.display()))?;
+        String::from_utf8(contents).context(format!("The script from {} is not text!", self.source))
+    }
+
+    /// The command running the verified copy at `cache` as root, as `kind`
+    /// when run through the helper.
+    pub fn command(&self, kind: ScriptKind, cache: &Path) -> ExternalCommand {
+        ExternalCommand {
+            request: Some(Box::new(Request::RunScript {
+                script: kind,
+                source: self.source.clone(),
+                sha256: self.sha256.clone().unwrap_or_default(),
+            })),
+            ..ExternalCommand::new("bash", [cache.to_string_lossy()])
+        }
+    }
+}
+
+/// Download `url` with `curl`, or `wget` if `curl` is not installed.
+fn download(runner: &mut dyn CommandRunner, url: &str) -> Result<Vec<u8>> {
+    let downloaders = [
+        ExternalCommand::new("curl", ["-fsSL", url]),
+        ExternalCommand::new("wget", ["-q", url, "-O", "-"]),
+    ];
+    for download in &downloaders {
+        match runner.output(download) {
+            Ok(output) if output.status.success() => return Ok(output.stdout),
+            Ok(output) => {
+                return Err(Error::CommandFailed {
+                    command: download.to_string(),
+                    status: output.status,
+                })
+                .context(format!("Failed to download {}!", url))
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => continue,
+            Err(error) => return Err(error).context(format!("Failed to download {}!", url)),
+        }
+    }
+    bail!(
+        "Neither curl nor wget is installed, so {} can not be downloaded! Install one of them or \
+         point the script at a local copy.",
+        url
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::{sha256, Script};
+    use crate::runner::RecordingRunner;
+
+    const SCRIPT: &str = "echo uninstalled";
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    fn pinned(source: &str, contents: &str) -> Script {
+        Script {
+            source: source.to_string(),
+            sha256: Some(sha256(contents.as_bytes())),
+        }
+    }
+
+    #[rstest]
+    fn test_fetch_local(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let path = temp_dir.path().join("uninstall.sh");
+        fs::write(&path, SCRIPT)?;
+        let cache = temp_dir.path().join("cache/uninstall.sh");
+        let mut runner = RecordingRunner::default();
+
+        let script = pinned(path.to_str().unwrap(), SCRIPT);
+        assert_eq!(script.fetch(&mut runner, &cache)?, SCRIPT);
+        assert!(runner.commands.is_empty());
+
+        // Test that a script which does not match its checksum is refused
+        let error = pinned(path.to_str().unwrap(), "echo other")
+            .fetch(&mut runner, &temp_dir.path().join("other.sh"))
+            .unwrap_err();
+        assert!(error.to_string().contains("does not match"));
+
+        // Test that an unpinned script is refused
+        assert!(Script::new(path.to_str().unwrap())
+            .fetch(&mut runner, &cache)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_fetch_remote(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let script = pinned("https://example.com/uninstall.sh", SCRIPT);
+        let cache = temp_dir.path().join("cache/uninstall.sh");
+
+        // Test that wget is used if curl is not installed
+        let mut runner = RecordingRunner::default()
+            .missing("curl")
+            .with_stdout("wget", SCRIPT);
+        assert_eq!(script.fetch(&mut runner, &cache)?, SCRIPT);
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "curl -fsSL https://example.com/uninstall.sh",
+                "wget -q https://example.com/uninstall.sh -O -"
+            ]
+        );
+
+        // Test that the cached copy is used without a downloader
+        let mut offline = RecordingRunner::default().missing("curl").missing("wget");
+        assert_eq!(script.fetch(&mut offline, &cache)?, SCRIPT);
+        assert!(offline.commands.is_empty());
+
+        // Test that a clear error is given without a downloader or a cached copy
+        fs::remove_file(&cache)?;
+        let error = script.fetch(&mut offline, &cache).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Neither curl nor wget is installed"));
+
+        Ok(())
+    }
+}
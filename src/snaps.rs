@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// snapd's state, which lists every installed snap. Only readable by root.
+pub const SNAPD_STATE_PATH: &str = "/var/lib/snapd/state.json";
+
+/// Where snaps are mounted, one directory per installed snap.
+pub const SNAP_MOUNT_DIR: &str = "/snap";
+
+/// Name of the file in the state directory listing the snaps without a known
+/// Flatpak replacement.
+pub const UNMATCHED_FILE: &str = "unmatched-snaps.txt";
+
+/// Flathub replacements of popular snaps, by snap name.
+const FLATHUB_IDS: &[(&str, &str)] = &[
+    ("android-studio", "com.google.AndroidStudio"),
+    ("audacity", "org.audacityteam.Audacity"),
+    ("bitwarden", "com.bitwarden.desktop"),
+    ("blender", "org.blender.Blender"),
+    ("chromium", "org.chromium.Chromium"),
+    ("code", "com.visualstudio.code"),
+    ("discord", "com.discordapp.Discord"),
+    ("element-desktop", "im.riot.Riot"),
+    ("firefox", "org.mozilla.firefox"),
+    ("gimp", "org.gimp.GIMP"),
+    ("gnome-calculator", "org.gnome.Calculator"),
+    ("gnome-characters", "org.gnome.Characters"),
+    ("gnome-logs", "org.gnome.Logs"),
+    ("gnome-system-monitor", "org.gnome.SystemMonitor"),
+    ("inkscape", "org.inkscape.Inkscape"),
+    (
+        "intellij-idea-community",
+        "com.jetbrains.IntelliJ-IDEA-Community",
+    ),
+    ("kdenlive", "org.kde.kdenlive"),
+    ("krita", "org.kde.krita"),
+    ("libreoffice", "org.libreoffice.LibreOffice"),
+    ("obs-studio", "com.obsproject.Studio"),
+    ("postman", "com.getpostman.Postman"),
+    ("pycharm-community", "com.jetbrains.PyCharm-Community"),
+    ("signal-desktop", "org.signal.Signal"),
+    ("skype", "com.skype.Client"),
+    ("slack", "com.slack.Slack"),
+    ("spotify", "com.spotify.Client"),
+    ("steam", "com.valvesoftware.Steam"),
+    ("telegram-desktop", "org.telegram.desktop"),
+    ("thunderbird", "org.mozilla.Thunderbird"),
+    ("vlc", "org.videolan.VLC"),
+    ("zoom-client", "us.zoom.Zoom"),
+];
+
+/// Whether `name` is a base, runtime or theme snap rather than an application.
+///
+/// snapd's state records the type of a snap, but the mount tree does not, and
+/// content snaps such as the GNOME runtimes are of the `app` type regardless.
+fn is_runtime(name: &str) -> bool {
+    let versioned = |prefix: &str| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+    };
+    matches!(
+        name,
+        "bare" | "core" | "snapd" | "snapd-desktop-integration" | "gtk-common-themes"
+    ) || versioned("core")
+        || versioned("gnome-")
+        || versioned("kde-frameworks-")
+        || versioned("mesa-")
+}
+
+/// The installed application snaps recorded in snapd's state.
+fn from_state(contents: &str) -> Result<Vec<String>> {
+    let state: Value = serde_json::from_str(contents)?;
+    let snaps = state["data"]["snaps"]
+        .as_object()
+        .context("The snapd state does not list any snaps!")?;
+    Ok(snaps
+        .iter()
+        .filter(|(_, snap)| snap["type"].as_str().is_none_or(|kind| kind == "app"))
+        .map(|(name, _)| name.clone())
+        .filter(|name| !is_runtime(name))
+        .collect())
+}
+
+/// The installed application snaps mounted in `mount_dir`.
+fn from_mount_dir(mount_dir: &Path) -> Result<Vec<String>> {
+    let mut snaps = Vec::new();
+    for entry in
+        fs::read_dir(mount_dir).context(format!("Failed to read {}!", mount_dir.display()))?
+    {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && name != "bin" && !is_runtime(&name) {
+            snaps.push(name);
+        }
+    }
+    Ok(snaps)
+}
+
+/// List the installed application snaps, from snapd's state at `state_path`
+/// or, if that can not be read, from the mount tree at `mount_dir`.
+pub fn installed(state_path: &Path, mount_dir: &Path) -> Result<Vec<String>> {
+    let mut snaps = match fs::read_to_string(state_path) {
+        Ok(contents) => {
+            from_state(&contents).context(format!("Failed to parse {}!", state_path.display()))?
+        },
+        Err(_) => from_mount_dir(mount_dir)?,
+    };
+    snaps.sort();
+    Ok(snaps)
+}
+
+/// A snap with a known Flatpak replacement.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Migration {
+    pub snap: String,
+    pub flatpak: &'static str,
+}
+
+/// The installed snaps, matched against their Flatpak replacements.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Inventory {
+    pub migrations: Vec<Migration>,
+    /// Snaps without a known replacement, to be migrated by hand.
+    pub unmatched: Vec<String>,
+}
+
+impl Inventory {
+    /// Match `snaps` against the bundled Flathub mapping.
+    pub fn new(snaps: Vec<String>) -> Self {
+        let mut inventory = Inventory::default();
+        for snap in snaps {
+            match FLATHUB_IDS.iter().find(|(name, _)| *name == snap) {
+                Some((_, flatpak)) => inventory.migrations.push(Migration { snap, flatpak }),
+                None => inventory.unmatched.push(snap),
+            }
+        }
+        inventory
+    }
+
+    /// Take the inventory of the snaps installed on the system, empty if
+    /// snapd is not installed.
+    pub fn detect() -> Self {
+        installed(Path::new(SNAPD_STATE_PATH), Path::new(SNAP_MOUNT_DIR))
+            .map(Inventory::new)
+            .unwrap_or_default()
+    }
+
+    /// The Flatpaks replacing the installed snaps.
+    pub fn flatpaks(&self) -> Vec<&'static str> {
+        self.migrations
+            .iter()
+            .map(|migration| migration.flatpak)
+            .collect()
+    }
+
+    /// Describe what will happen to the installed snaps.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        if !self.migrations.is_empty() {
+            description.push_str("These snaps will be replaced with Flatpaks from Flathub:\n");
+            for migration in &self.migrations {
+                description.push_str(&format!("  {} -> {}\n", migration.snap, migration.flatpak));
+            }
+        }
+        if !self.unmatched.is_empty() {
+            description.push_str(
+                "These snaps have no known Flatpak replacement and will have to be migrated by \
+                 hand:\n",
+            );
+            for snap in &self.unmatched {
+                description.push_str(&format!("  {}\n", snap));
+            }
+        }
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::{Inventory, Migration};
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_installed(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let state_path = temp_dir.path().join("state.json");
+        let mount_dir = temp_dir.path().join("snap");
+        for snap in ["bin", "core20", "firefox", "gnome-3-38-2004", "hello"] {
+            fs::create_dir_all(mount_dir.join(snap))?;
+        }
+
+        // Test that the mount tree is used if snapd's state can not be read
+        assert_eq!(
+            super::installed(&state_path, &mount_dir)?,
+            ["firefox", "hello"]
+        );
+
+        fs::write(
+            &state_path,
+            r#"{"data": {"snaps": {
+                "vlc": {"type": "app"},
+                "core22": {"type": "base"},
+                "snapd": {"type": "snapd"},
+                "gtk-common-themes": {"type": "app"},
+                "code": {"type": "app"}
+            }}}"#,
+        )?;
+        assert_eq!(super::installed(&state_path, &mount_dir)?, ["code", "vlc"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inventory() {
+        let inventory = Inventory::new(vec!["firefox".to_string(), "hello".to_string()]);
+        assert_eq!(
+            inventory.migrations,
+            [Migration {
+                snap: "firefox".to_string(),
+                flatpak: "org.mozilla.firefox",
+            }]
+        );
+        assert_eq!(inventory.unmatched, ["hello"]);
+        assert_eq!(inventory.flatpaks(), ["org.mozilla.firefox"]);
+    }
+}
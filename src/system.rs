@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use anyhow::{Context, Result};
+
+/// Location of dpkg's status database.
+pub const DPKG_STATUS_PATH: &str = "/var/lib/dpkg/status";
+
+/// Location of the release of the running kernel.
+pub const OSRELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+/// Read the names of all installed packages from a dpkg status database.
+///
+/// Only packages whose status is `install ok installed` are returned, so
+/// removed packages that still have their config files around are skipped.
+///
+/// # Arguments
+///
+/// * `status_path` - Path to the dpkg status database, usually
+///   [`DPKG_STATUS_PATH`].
+pub fn installed_packages(status_path: &Path) -> Result<HashSet<String>> {
+    let database = fs::read_to_string(status_path).context(format!(
+        "Failed to read the dpkg status database at {}!",
+        status_path.display()
+    ))?;
+
+    let mut packages = HashSet::new();
+    for stanza in database.split("\n\n") {
+        let mut name = None;
+        let mut installed = false;
+
+        for line in stanza.lines() {
+            if let Some(value) = line.strip_prefix("Package:") {
+                name = Some(value.trim());
+            } else if let Some(value) = line.strip_prefix("Status:") {
+                installed = value.split_whitespace().nth(2) == Some("installed");
+            }
+        }
+
+        if let (Some(name), true) = (name, installed) {
+            packages.insert(name.to_string());
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Read the release of the running kernel, as in `uname -r`.
+pub fn running_release(osrelease_path: &Path) -> Result<String> {
+    let release = fs::read_to_string(osrelease_path).context(format!(
+        "Failed to read the kernel release from {}!",
+        osrelease_path.display()
+    ))?;
+    Ok(release.trim().to_string())
+}
+
+/// Find an executable in the directories listed in `PATH`.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use indoc::indoc;
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[rstest]
+    fn test_installed_packages(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let status_path = temp_dir.path().join("status");
+        fs::write(
+            &status_path,
+            indoc!(
+                "
+                Package: snapd
+                Status: install ok installed
+                Version: 2.56
+
+                Package: flatpak
+                Status: deinstall ok config-files
+                Version: 1.12
+
+                Package: linux-xanmod
+                Architecture: amd64
+                Status: install ok installed
+                "
+            ),
+        )?;
+
+        let packages = super::installed_packages(&status_path)?;
+        assert!(packages.contains("snapd"));
+        assert!(packages.contains("linux-xanmod"));
+        // Test that removed packages are not reported as installed
+        assert!(!packages.contains("flatpak"));
+
+        Ok(())
+    }
+}
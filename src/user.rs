@@ -0,0 +1,296 @@
+use std::env::var;
+use std::os::unix::fs::{lchown, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use anyhow::{bail, Context, Result};
+
+use crate::runner::{CommandRunner, ExternalCommand};
+
+/// Location of the passwd database, read if `getent` is not installed.
+pub const PASSWD_PATH: &str = "/etc/passwd";
+
+/// Status of the running process, which holds its user ids.
+const PROC_STATUS_PATH: &str = "/proc/self/status";
+
+/// An account from the passwd database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+/// Read the accounts from a passwd database, skipping malformed lines.
+fn parse_passwd(contents: &str) -> Vec<Account> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            match fields[..] {
+                [name, _, uid, gid, _, home, ..] => Some(Account {
+                    name: name.to_string(),
+                    uid: uid.parse().ok()?,
+                    gid: gid.parse().ok()?,
+                    home: PathBuf::from(home),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The effective user id from the status of a process.
+fn effective_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().nth(1))
+        .and_then(|id| id.parse().ok())
+}
+
+/// The effective user id of rhino-config, if it can be told.
+pub fn euid() -> Option<u32> {
+    fs::read_to_string(PROC_STATUS_PATH)
+        .ok()
+        .and_then(|status| effective_uid(&status))
+}
+
+/// Whether rhino-config is running as root.
+pub fn is_root() -> bool { euid() == Some(0) }
+
+/// Who rhino-config is acting on behalf of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invoker {
+    /// Running as the user, or as a user that could not be identified.
+    User,
+    /// Running as root through `sudo` or `pkexec` on behalf of an account.
+    Elevated(Account),
+    /// Running as root with no way of telling on whose behalf.
+    Root,
+}
+
+/// Look up an account by name, or by user id if `key` is a number.
+///
+/// Accounts are resolved through `getent`, so that those from LDAP or
+/// systemd-homed are found too, falling back to the passwd database if
+/// `getent` is not installed.
+fn lookup(runner: &mut dyn CommandRunner, key: &str) -> Result<Option<Account>> {
+    let passwd = match runner.output(&ExternalCommand::new("getent", ["passwd", key])) {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        },
+        // getent fails if there is no such account
+        Ok(_) => return Ok(None),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            fs::read_to_string(PASSWD_PATH).context(format!("Failed to read {}!", PASSWD_PATH))?
+        },
+        Err(error) => return Err(error).context(format!("Failed to look up {}!", key)),
+    };
+    let uid = key.parse::<u32>().ok();
+    Ok(parse_passwd(&passwd)
+        .into_iter()
+        .find(|account| Some(account.uid) == uid || account.name == key))
+}
+
+/// Work out the invoker from the effective user id, `SUDO_USER` and
+/// `PKEXEC_UID`, looking up the account with `runner`.
+fn resolve(
+    euid: Option<u32>,
+    sudo_user: Option<&str>,
+    pkexec_uid: Option<&str>,
+    runner: &mut dyn CommandRunner,
+) -> Result<Invoker> {
+    if euid != Some(0) {
+        return Ok(Invoker::User);
+    }
+
+    if let Some(name) = sudo_user.filter(|&name| !name.is_empty() && name != "root") {
+        return match lookup(runner, name)? {
+            Some(account) => Ok(Invoker::Elevated(account)),
+            None => bail!("SUDO_USER is set to {}, who has no account!", name),
+        };
+    }
+    if let Some(uid) = pkexec_uid.filter(|uid| !uid.is_empty()) {
+        let uid: u32 = uid.parse().context(format!(
+            "PKEXEC_UID is set to {}, which is not a user id!",
+            uid
+        ))?;
+        return match lookup(runner, &uid.to_string())? {
+            Some(account) if account.uid != 0 => Ok(Invoker::Elevated(account)),
+            Some(_) => Ok(Invoker::Root),
+            None => bail!("PKEXEC_UID is set to {}, who has no account!", uid),
+        };
+    }
+    Ok(Invoker::Root)
+}
+
+impl Invoker {
+    /// Work out who rhino-config is running for, looking up their account
+    /// with `runner`.
+    pub fn detect(runner: &mut dyn CommandRunner) -> Result<Self> {
+        resolve(
+            euid(),
+            var("SUDO_USER").ok().as_deref(),
+            var("PKEXEC_UID").ok().as_deref(),
+            runner,
+        )
+    }
+}
+
+impl Account {
+    /// Hand `path` and everything below it back to the account, along with
+    /// the directories above it that root created in the home directory.
+    ///
+    /// Paths outside of the home directory are left alone.
+    pub fn chown(&self, path: &Path) -> Result<()> {
+        if !path.starts_with(&self.home) || !path.exists() {
+            return Ok(());
+        }
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.starts_with(&self.home) || ancestor == self.home {
+                break;
+            }
+            if fs::symlink_metadata(ancestor)?.uid() == 0 {
+                self.chown_one(ancestor)?;
+            }
+        }
+        self.chown_tree(path)
+    }
+
+    fn chown_tree(&self, path: &Path) -> Result<()> {
+        self.chown_one(path)?;
+        if fs::symlink_metadata(path)?.is_dir() {
+            for entry in
+                fs::read_dir(path).context(format!("Failed to read {}!", path.display()))?
+            {
+                self.chown_tree(&entry?.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn chown_one(&self, path: &Path) -> Result<()> {
+        lchown(path, Some(self.uid), Some(self.gid)).context(format!(
+            "Failed to hand {} back to {}!",
+            path.display(),
+            self.name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::PathBuf;
+
+    use indoc::indoc;
+    use rstest::*;
+    use tempfile::{tempdir, TempDir};
+
+    use super::{Account, Invoker};
+    use crate::runner::RecordingRunner;
+
+    const ROOT: &str = "root:x:0:0:root:/root:/bin/bash\n";
+    const RHINO: &str = "rhino:x:1000:1000:Rolling Rhino,,,:/home/rhino:/bin/bash\n";
+
+    const PASSWD: &str = indoc!(
+        "
+        root:x:0:0:root:/root:/bin/bash
+        broken line
+        rhino:x:1000:1000:Rolling Rhino,,,:/home/rhino:/bin/bash
+        "
+    );
+
+    fn rhino() -> Account {
+        Account {
+            name: "rhino".to_string(),
+            uid: 1000,
+            gid: 1000,
+            home: PathBuf::from("/home/rhino"),
+        }
+    }
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(super::parse_passwd(PASSWD)[1], rhino());
+        assert_eq!(super::parse_passwd(PASSWD).len(), 2);
+
+        let status = "Name:\trhino-config\nUid:\t1000\t0\t0\t0\nGid:\t1000\t1000\t1000\t1000\n";
+        assert_eq!(super::effective_uid(status), Some(0));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let resolve = |euid, sudo_user, pkexec_uid| {
+            let mut runner = RecordingRunner::default()
+                .with_stdout("getent passwd rhino", RHINO)
+                .with_stdout("getent passwd 1000", RHINO)
+                .with_stdout("getent passwd 0", ROOT)
+                .fail_on("getent passwd nobody");
+            super::resolve(Some(euid), sudo_user, pkexec_uid, &mut runner)
+        };
+
+        // Test that the environment is ignored unless running as root
+        assert_eq!(resolve(1000, Some("root"), None).unwrap(), Invoker::User);
+        assert_eq!(
+            resolve(0, Some("rhino"), None).unwrap(),
+            Invoker::Elevated(rhino())
+        );
+        assert_eq!(
+            resolve(0, None, Some("1000")).unwrap(),
+            Invoker::Elevated(rhino())
+        );
+        // Test that root without a target user is told apart
+        assert_eq!(resolve(0, Some("root"), None).unwrap(), Invoker::Root);
+        assert_eq!(resolve(0, None, Some("0")).unwrap(), Invoker::Root);
+        assert_eq!(resolve(0, None, None).unwrap(), Invoker::Root);
+
+        assert!(resolve(0, Some("nobody"), None).is_err());
+        assert!(resolve(0, None, Some("rhino")).is_err());
+    }
+
+    #[test]
+    fn test_lookup() -> Result<(), Box<dyn Error>> {
+        // Test that accounts are looked up through getent
+        let mut runner = RecordingRunner::default().with_stdout("getent passwd rhino", RHINO);
+        assert_eq!(super::lookup(&mut runner, "rhino")?, Some(rhino()));
+        assert_eq!(runner.command_lines(), ["getent passwd rhino"]);
+
+        // Test that the passwd database is read without getent
+        let mut runner = RecordingRunner::default().missing("getent");
+        assert!(super::lookup(&mut runner, "0")?.is_some_and(|account| account.name == "root"));
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_chown(temp_dir: TempDir) -> Result<(), Box<dyn Error>> {
+        let metadata = fs::metadata(temp_dir.path())?;
+        let account = Account {
+            name: "rhino".to_string(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            home: temp_dir.path().to_path_buf(),
+        };
+        let root = temp_dir.path().join(".config/rhino");
+        fs::create_dir_all(root.join("config"))?;
+        fs::write(root.join("config/liquorix"), "")?;
+
+        account.chown(&root)?;
+        assert_eq!(
+            fs::metadata(root.join("config/liquorix"))?.uid(),
+            account.uid
+        );
+        // Test that paths outside of the home directory are left alone
+        account.chown(&PathBuf::from("/"))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,389 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::{Command, CommandFactory};
+use rhino_config::backup::Backup;
+use rhino_config::commands::{disable, enable, switch_kernel};
+use rhino_config::output::{Action, Report};
+use rhino_config::snaps::Inventory;
+use rhino_config::{Kernel, Operation, Plan, RhinoOption, Store, XanmodVariant};
+
+use crate::cli::Cli;
+use crate::prompt::{Choice, Prompt};
+
+/// The kernel families offered by the wizard, `None` being the stock kernel.
+const FAMILIES: [Option<&str>; 5] = [
+    None,
+    Some("xanmod"),
+    Some("liquorix"),
+    Some("libre"),
+    Some("mainline"),
+];
+
+/// The family of `kernel`, as named by its `switch-kernel enable` subcommand.
+fn family(kernel: Kernel) -> &'static str {
+    match kernel {
+        Kernel::Xanmod(_) => "xanmod",
+        Kernel::Liquorix => "liquorix",
+        Kernel::Libre => "libre",
+        Kernel::Mainline => "mainline",
+    }
+}
+
+/// The one-line help of `name`, so that the wizard describes options the
+/// same way `--help` does.
+fn about(command: &Command, name: &str) -> String {
+    command
+        .find_subcommand(name)
+        .and_then(Command::get_about)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The help of the flag that selects `variant`.
+fn variant_help<'a>(xanmod: &Command<'a>, variant: XanmodVariant) -> Option<&'a str> {
+    xanmod
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(&variant.name().replace('_', "-")))
+        .and_then(|arg| arg.get_help())
+}
+
+/// Ask which kernel to use, returning the operation that switches to it.
+fn kernel_step(store: &Store, prompt: &Prompt) -> Result<Option<Operation>> {
+    let cli = Cli::command();
+    let enable_kernel = cli
+        .find_subcommand("switch-kernel")
+        .and_then(|command| command.find_subcommand("enable"))
+        .expect("switch-kernel has an enable subcommand");
+
+    let current = store.load()?.kernel();
+    let choices = FAMILIES
+        .iter()
+        .map(|family| match family {
+            Some(family) => Choice {
+                name: family.to_string(),
+                description: about(enable_kernel, family),
+            },
+            None => Choice {
+                name: "stock".to_string(),
+                description: "Keep the kernel shipped with Ubuntu".to_string(),
+            },
+        })
+        .collect::<Vec<_>>();
+    let default = FAMILIES
+        .iter()
+        .position(|&family| family == current.map(self::family))
+        .unwrap_or_default();
+    let message = match current {
+        Some(kernel) => format!(
+            "The `{}` kernel is currently enabled. Which kernel do you wish to use?",
+            RhinoOption::Kernel(kernel)
+        ),
+        None => {
+            "The stock kernel is currently in use. Which kernel do you wish to use?".to_string()
+        },
+    };
+    // Switching kernels is never proposed, so that `--yes` keeps the kernel
+    let selected = match FAMILIES[prompt.choose(&message, &choices, default, default)?] {
+        None => None,
+        Some("xanmod") => {
+            let xanmod = enable_kernel
+                .find_subcommand("xanmod")
+                .expect("switch-kernel enable has a xanmod subcommand");
+            let choices = XanmodVariant::ALL
+                .iter()
+                .map(|variant| Choice {
+                    name: variant.name().to_string(),
+                    description: variant_help(xanmod, *variant)
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+                .collect::<Vec<_>>();
+            let default = XanmodVariant::ALL
+                .iter()
+                .position(|&variant| current == Some(Kernel::Xanmod(variant)))
+                .unwrap_or_default();
+            let variant = prompt.choose(
+                "Which XanMod variant do you wish to use?",
+                &choices,
+                default,
+                default,
+            )?;
+            Some(Kernel::Xanmod(XanmodVariant::ALL[variant]))
+        },
+        Some(family) => Kernel::all()
+            .into_iter()
+            .find(|&kernel| self::family(kernel) == family),
+    };
+
+    match selected {
+        _ if selected == current => Ok(None),
+        // Picking another kernel in the wizard is an explicit request to replace the current one
+        Some(selected) => Ok(Some(switch_kernel::enable(store, selected, true)?)),
+        None => current
+            .map(|current| switch_kernel::disable(store, current))
+            .transpose(),
+    }
+}
+
+/// The help of the `--flag` of `subcommand`.
+fn flag_help<'a>(cli: &Command<'a>, subcommand: &str, flag: &str) -> Option<&'a str> {
+    cli.find_subcommand(subcommand)?
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(flag))
+        .and_then(|arg| arg.get_help())
+}
+
+/// Ask whether to keep `option` as it is or to flip it, returning `true` if
+/// it should be flipped.
+///
+/// Enabling a disabled option is proposed, disabling one never is.
+fn review_step(prompt: &Prompt, option: RhinoOption, enabled: bool) -> Result<bool> {
+    let cli = Cli::command();
+    let (state, action) = if enabled {
+        ("enabled", "disable")
+    } else {
+        ("disabled", "enable")
+    };
+    let flag = option.to_string();
+    let choices = [
+        Choice {
+            name: "keep".to_string(),
+            description: format!("Keep {} {}", option, state),
+        },
+        Choice {
+            name: action.to_string(),
+            description: flag_help(&cli, action, &flag)
+                .unwrap_or_default()
+                .to_string(),
+        },
+    ];
+
+    Ok(prompt.choose(
+        &format!("`{}` is currently {}.", option, state),
+        &choices,
+        0,
+        usize::from(!enabled),
+    )? == 1)
+}
+
+/// Offer to restore the latest backup of `snap_path` once snapd has been
+/// reinstalled, returning the backup to restore.
+pub fn restore_step(
+    store: &Store,
+    prompt: &Prompt,
+    snap_path: Option<&Path>,
+) -> Result<Option<Backup>> {
+    let backup = match snap_path {
+        Some(snap_path) => Backup::latest(&store.backup_dir(), snap_path)?,
+        None => None,
+    };
+    match backup {
+        Some(backup) => {
+            let message = format!(
+                "{} was backed up to {} when snapd was purged. Restore it once snapd is \
+                 reinstalled?",
+                backup.source.display(),
+                backup.archive.display()
+            );
+            Ok(prompt.ask(&message)?.then_some(backup))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Step through each option one-by-one, letting the user keep, enable or
+/// disable it, and returning the plan of the changes they asked for.
+///
+/// The pending changes are summarized at the end and only returned if the
+/// user confirms them, unless `dry_run` is set as nothing is applied anyway.
+pub fn wizard(
+    store: &Store,
+    prompt: &Prompt,
+    report: &Report,
+    snap_path: Option<&Path>,
+    dry_run: bool,
+) -> Result<Plan> {
+    let config = store.load()?;
+    let state_dir = store.state_dir();
+    let mut plan = Plan::default();
+
+    if let Some(operation) = kernel_step(store, prompt)? {
+        plan.push(operation);
+    }
+
+    let snapdpurge = config.is_enabled(RhinoOption::Snapdpurge);
+    // Show which applications purging snapd affects before asking about it
+    let inventory = if snapdpurge {
+        Inventory::default()
+    } else {
+        Inventory::detect()
+    };
+    print!("{}", inventory.describe());
+    if review_step(prompt, RhinoOption::Snapdpurge, snapdpurge)? {
+        plan.push(if snapdpurge {
+            disable::snapdpurge(store, &state_dir, restore_step(store, prompt, snap_path)?)?
+        } else {
+            let snap_path = snap_path.context("Unable to find HOME environment variable!")?;
+            let backup = prompt.ask(&format!(
+                "Back up {} before it is removed?",
+                snap_path.display()
+            ))?;
+            enable::snapdpurge(store, snap_path, &state_dir, &inventory, backup)?
+        });
+    }
+
+    let pacstall = config.is_enabled(RhinoOption::Pacstall);
+    if review_step(prompt, RhinoOption::Pacstall, pacstall)? {
+        plan.push(if pacstall {
+            disable::pacstall(store)?
+        } else {
+            enable::pacstall(store, &state_dir)?
+        });
+    }
+
+    if plan.operations.is_empty() {
+        report.progress("No changes were made to the Rhino configuration.");
+        return Ok(plan);
+    }
+
+    println!("\nPending changes:");
+    for operation in &plan.operations {
+        let action = match operation.action {
+            Action::Enable => "enable",
+            Action::Disable => "disable",
+        };
+        println!("  {} {}", action, operation.option);
+    }
+
+    if dry_run {
+        return Ok(plan);
+    }
+
+    // Purging snapd is destructive, so it is only done on an explicit yes
+    let purges_snapd = plan.operations.iter().any(|operation| {
+        operation.option == RhinoOption::Snapdpurge && operation.action == Action::Enable
+    });
+    let confirmed = if purges_snapd {
+        prompt.ask_default_no("Apply these changes?")?
+    } else {
+        prompt.ask("Apply these changes?")?
+    };
+    if !confirmed {
+        report.progress("No changes were made to the Rhino configuration.");
+        return Ok(Plan::default());
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use clap::CommandFactory;
+    use rhino_config::output::{Action, Format, Report};
+    use rhino_config::{RhinoOption, Store, XanmodVariant};
+    use rstest::{fixture, rstest};
+    use tempfile::{tempdir, TempDir};
+
+    use super::FAMILIES;
+    use crate::cli::Cli;
+    use crate::prompt::Prompt;
+
+    #[fixture]
+    fn temp_dir() -> TempDir { tempdir().unwrap() }
+
+    #[fixture]
+    fn report() -> Report { Report::new(Format::Text, "test") }
+
+    #[rstest]
+    fn test_yes(temp_dir: TempDir, report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(&temp_dir.path().join("rhino"));
+        let snap_dir = tempdir()?;
+
+        // Test that `--yes` enables every disabled option and keeps the kernel
+        let plan = super::wizard(
+            &store,
+            &Prompt::new(true, false),
+            &report,
+            Some(snap_dir.path()),
+            false,
+        )?;
+        let changes = plan
+            .operations
+            .iter()
+            .map(|operation| (operation.option, operation.action))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                (RhinoOption::Snapdpurge, Action::Enable),
+                (RhinoOption::Pacstall, Action::Enable)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_assume_no(temp_dir: TempDir, report: Report) -> Result<(), Box<dyn Error>> {
+        let store = Store::new(&temp_dir.path().join("rhino"));
+        let snap_dir = tempdir()?;
+
+        // Test that `--assume-no` keeps everything as it is
+        let plan = super::wizard(
+            &store,
+            &Prompt::new(false, true),
+            &report,
+            Some(snap_dir.path()),
+            false,
+        )?;
+        assert!(plan.operations.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_descriptions() {
+        let cli = Cli::command();
+        let enable_kernel = cli
+            .find_subcommand("switch-kernel")
+            .and_then(|command| command.find_subcommand("enable"))
+            .unwrap();
+
+        // Test that every kernel offered by the wizard has a description
+        for family in FAMILIES.into_iter().flatten() {
+            assert!(
+                !super::about(enable_kernel, family).is_empty(),
+                "{}",
+                family
+            );
+        }
+        assert_eq!(
+            super::about(enable_kernel, "liquorix"),
+            "Enable the Liquorix kernel"
+        );
+
+        // Test that the options reviewed by the wizard have descriptions
+        for option in [RhinoOption::Pacstall, RhinoOption::Snapdpurge] {
+            for subcommand in ["enable", "disable"] {
+                assert!(
+                    super::flag_help(&cli, subcommand, &option.to_string()).is_some(),
+                    "{} --{}",
+                    subcommand,
+                    option
+                );
+            }
+        }
+
+        // Test that every XanMod variant has a description
+        let xanmod = enable_kernel.find_subcommand("xanmod").unwrap();
+        for variant in XanmodVariant::ALL {
+            assert!(
+                super::variant_help(xanmod, variant).is_some(),
+                "{}",
+                variant.name()
+            );
+        }
+    }
+}